@@ -0,0 +1,328 @@
+use anyhow::Result;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Versions kept per file when the configuration does not say otherwise.
+pub const DEFAULT_KEEP: usize = 20;
+
+/// Where the previous contents of overwritten files are kept.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Defaults to the `backups` directory under inkspect's data directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// How many versions of each file are kept; older ones are dropped.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_keep() -> usize {
+    DEFAULT_KEEP
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+            keep: DEFAULT_KEEP,
+        }
+    }
+}
+
+/// The default store, under the platform's data directory, e.g.
+/// `~/.local/share/inkspect/backups`.
+pub fn default_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("rs", "", "inkspect").map(|dirs| dirs.data_dir().join("backups"))
+}
+
+/// What a file held before inkspect overwrote it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Version {
+    pub path: PathBuf,
+    pub timestamp: Timestamp,
+    pub style: String,
+    pub provider: String,
+    pub content: String,
+}
+
+/// A saved [`Version`] and the entry it is stored in.
+#[derive(Debug)]
+pub struct Entry {
+    file: PathBuf,
+    pub version: Version,
+}
+
+/// The backup store: one directory per file, named after a hash of its
+/// absolute path, holding one JSON entry per version.
+pub struct Store {
+    dir: PathBuf,
+    keep: usize,
+}
+
+impl Store {
+    /// The store described by the configuration, unless backups are off.
+    pub fn open(config: &BackupConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self {
+            dir: config.dir.clone()?,
+            keep: config.keep.max(1),
+        })
+    }
+
+    fn file_dir(&self, path: &Path) -> Result<PathBuf> {
+        let hash = Sha256::digest(absolute(path)?.to_string_lossy().as_bytes());
+        let key: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        Ok(self.dir.join(key))
+    }
+
+    /// Saves `content` as the newest version of `path`, dropping the oldest
+    /// versions beyond the configured number.
+    pub fn record(&self, path: &Path, content: String, style: &str, provider: &str) -> Result<()> {
+        let dir = self.file_dir(path)?;
+        fs::create_dir_all(&dir).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to create backup directory '{}': {}",
+                dir.display(),
+                e
+            )
+        })?;
+        let version = Version {
+            path: absolute(path)?,
+            timestamp: Timestamp::now(),
+            style: style.to_string(),
+            provider: provider.to_string(),
+            content,
+        };
+        let mut nanos = version.timestamp.as_nanosecond();
+        while dir.join(format!("{}.json", nanos)).exists() {
+            nanos += 1;
+        }
+        write_atomic(
+            &dir.join(format!("{}.json", nanos)),
+            &serde_json::to_string_pretty(&version)?,
+        )?;
+
+        for entry in self.versions(path)?.into_iter().skip(self.keep) {
+            self.remove(&entry)?;
+        }
+        Ok(())
+    }
+
+    fn read_dir(dir: &Path) -> Result<Vec<Entry>> {
+        let mut files: Vec<(i128, PathBuf)> = Vec::new();
+        for item in fs::read_dir(dir)? {
+            let file = item?.path();
+            if let Some(nanos) = file
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+                && file.extension().is_some_and(|ext| ext == "json")
+            {
+                files.push((nanos, file));
+            }
+        }
+        files.sort_by_key(|(nanos, _)| std::cmp::Reverse(*nanos));
+        files
+            .into_iter()
+            .map(|(_, file)| {
+                let version = serde_json::from_str(&fs::read_to_string(&file)?).map_err(|e| {
+                    anyhow::anyhow!("Corrupt backup entry '{}': {}", file.display(), e)
+                })?;
+                Ok(Entry { file, version })
+            })
+            .collect()
+    }
+
+    /// The saved versions of `path`, newest first.
+    pub fn versions(&self, path: &Path) -> Result<Vec<Entry>> {
+        let dir = self.file_dir(path)?;
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        Self::read_dir(&dir)
+    }
+
+    /// The newest version across every file in the store.
+    pub fn latest(&self) -> Result<Option<Entry>> {
+        if !self.dir.is_dir() {
+            return Ok(None);
+        }
+        let mut latest: Option<Entry> = None;
+        for item in fs::read_dir(&self.dir)? {
+            let dir = item?.path();
+            if !dir.is_dir() {
+                continue;
+            }
+            if let Some(entry) = Self::read_dir(&dir)?.into_iter().next()
+                && latest
+                    .as_ref()
+                    .is_none_or(|l| entry.version.timestamp > l.version.timestamp)
+            {
+                latest = Some(entry);
+            }
+        }
+        Ok(latest)
+    }
+
+    pub fn remove(&self, entry: &Entry) -> Result<()> {
+        fs::remove_file(&entry.file).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to remove backup entry '{}': {}",
+                entry.file.display(),
+                e
+            )
+        })
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf> {
+    Ok(match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => std::path::absolute(path)?,
+    })
+}
+
+/// Replaces `path` with `content` through a temp file renamed over it, so
+/// that a crash mid-write leaves either the old or the new file, never a
+/// truncated one. An existing file keeps its permissions; a new one gets the
+/// same as with `fs::write`.
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    // Write through symlinks rather than replacing them.
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let error =
+        |e: &dyn std::fmt::Display| anyhow::anyhow!("Failed to write '{}': {}", path.display(), e);
+    let existing = fs::metadata(&path)
+        .ok()
+        .map(|metadata| metadata.permissions());
+    let mut builder = tempfile::Builder::new();
+    #[cfg(unix)]
+    if existing.is_none() {
+        use std::os::unix::fs::PermissionsExt;
+        // Temp files are private to the owner; the umask narrows this instead.
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    let mut temp_file = builder.tempfile_in(dir).map_err(|e| error(&e))?;
+    temp_file
+        .write_all(content.as_bytes())
+        .and_then(|()| temp_file.as_file().sync_all())
+        .map_err(|e| error(&e))?;
+    if let Some(permissions) = existing {
+        temp_file
+            .as_file()
+            .set_permissions(permissions)
+            .map_err(|e| error(&e))?;
+    }
+    temp_file.persist(&path).map_err(|e| error(&e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(dir: &Path, keep: usize) -> Store {
+        Store::open(&BackupConfig {
+            dir: Some(dir.join("backups")),
+            keep,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_open() {
+        assert!(Store::open(&BackupConfig::default()).is_none());
+        assert!(
+            Store::open(&BackupConfig {
+                enabled: false,
+                dir: Some("/tmp/backups".into()),
+                keep: 3,
+            })
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_record_and_list_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (notes, other) = (dir.path().join("notes.md"), dir.path().join("other.md"));
+        std::fs::write(&notes, "current").unwrap();
+        let store = store(dir.path(), 2);
+
+        assert!(store.versions(&notes).unwrap().is_empty());
+        assert!(store.latest().unwrap().is_none());
+        for content in ["first", "second", "third"] {
+            store
+                .record(&notes, content.to_string(), "refine", "claude")
+                .unwrap();
+        }
+        store
+            .record(&other, "draft".to_string(), "code-spec", "ollama")
+            .unwrap();
+
+        let versions = store.versions(&notes).unwrap();
+        let contents: Vec<&str> = versions
+            .iter()
+            .map(|e| e.version.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["third", "second"]);
+        assert_eq!(versions[0].version.style, "refine");
+        assert_eq!(versions[0].version.provider, "claude");
+        assert_eq!(
+            versions[0].version.path,
+            std::fs::canonicalize(&notes).unwrap()
+        );
+
+        let latest = store.latest().unwrap().unwrap();
+        assert_eq!(latest.version.content, "draft");
+        store.remove(&latest).unwrap();
+        assert!(store.versions(&other).unwrap().is_empty());
+        assert_eq!(store.latest().unwrap().unwrap().version.content, "third");
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        write_atomic(&path, "echo one").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "echo one");
+        // A new file is created as `fs::write` would create it.
+        let reference = dir.path().join("reference");
+        std::fs::write(&reference, "").unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), mode(&reference));
+        std::fs::remove_file(&reference).unwrap();
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+        write_atomic(&path, "echo two").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "echo two");
+        assert_eq!(mode(&path), 0o750);
+        // No temp files are left behind.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let link = dir.path().join("link.sh");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        write_atomic(&link, "echo three").unwrap();
+        assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "echo three");
+    }
+}
@@ -0,0 +1,278 @@
+use crate::backup::Store;
+use crate::chunk::ChunkingOverrides;
+use crate::cli::{BatchArgs, OutputFormat};
+use crate::config::Config;
+use crate::context;
+use crate::core::{
+    BackendFactory, Step, build_prompt, map_reduce, print_json_list, request_json, resolve_step,
+    spinner_style, start_spinner, template_vars, validate_model, write_file,
+};
+use crate::llm::r#trait::{GenerationParams, LlmBackend, LlmRequest};
+use anyhow::Result;
+use console::style;
+use futures_util::StreamExt;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// What happened to one file of a batch.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum BatchStatus {
+    Ok,
+    Failed,
+    /// Not processed because an earlier file failed with `--fail-fast`.
+    Skipped,
+}
+
+/// An entry of the `batch` summary.
+#[derive(Serialize, Debug)]
+struct BatchReport {
+    path: PathBuf,
+    status: BatchStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The deepest directory holding every path, which `--output-dir` mirrors.
+fn common_dir(paths: &[PathBuf]) -> PathBuf {
+    let mut base = paths
+        .first()
+        .and_then(|path| path.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    for path in paths {
+        while !path.starts_with(&base) && base.pop() {}
+    }
+    base
+}
+
+/// Optimizes a single file of a batch and writes the result to `destination`.
+async fn optimize_file(
+    llm_backend: &dyn LlmBackend,
+    step: &Step,
+    mut request: LlmRequest,
+    mut vars: BTreeMap<String, String>,
+    path: &Path,
+    destination: &Path,
+    store: Option<&Store>,
+) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read input file: {}", e))?;
+    if content.trim().is_empty() {
+        return Err(anyhow::anyhow!("Input is empty"));
+    }
+    vars.insert("filename".to_string(), path.display().to_string());
+
+    let response = if let Some(chunks) = step.chunks(&content) {
+        let prompts = chunks
+            .iter()
+            .map(|chunk| build_prompt(&step.style_text, chunk, vars.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        map_reduce(
+            llm_backend,
+            &request,
+            step,
+            prompts,
+            &vars,
+            &ProgressBar::hidden(),
+        )
+        .await?
+        .text
+    } else {
+        request.prompt = build_prompt(&step.style_text, &content, vars)?;
+        match &step.schema {
+            Some(schema) => {
+                request_json(llm_backend, &request, step, schema)
+                    .await?
+                    .text
+            }
+            None => step
+                .postprocessor
+                .apply(&llm_backend.request(&request).await?.text),
+        }
+    };
+    if response.trim().is_empty() {
+        return Err(anyhow::anyhow!("The model returned an empty response"));
+    }
+
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_file(
+        destination,
+        &response,
+        store,
+        &(step.label.clone(), step.provider_name.clone()),
+    )
+}
+
+/// Runs one style over many files, `--jobs` at a time. A failing file does
+/// not stop the others unless `--fail-fast` is given.
+pub async fn run(
+    args: BatchArgs,
+    config: &Config,
+    format: OutputFormat,
+    create_backend: &BackendFactory,
+) -> Result<()> {
+    let step = resolve_step(
+        config,
+        args.style.as_deref(),
+        None,
+        args.provider,
+        args.model.as_ref(),
+        &GenerationParams::default(),
+        &ChunkingOverrides::default(),
+    )?;
+    let paths = context::expand(&args.paths, "Path")?;
+    let vars = template_vars(
+        &step.templates(),
+        paths.first().and_then(|path| path.to_str()),
+        args.vars,
+    )?;
+    let llm_backend = create_backend(&step.provider_name, &step.provider)?;
+    if let Some(model) = &args.model {
+        validate_model(llm_backend.as_ref(), &step.provider_name, model).await?;
+    }
+    let request = LlmRequest {
+        system_prompt: if args.no_system_prompt {
+            None
+        } else {
+            config.llm.system_prompt.clone()
+        },
+        params: step.params.clone(),
+        json_schema: step.schema.as_ref().map(|s| s.value().clone()),
+        ..Default::default()
+    };
+
+    let base = common_dir(&paths);
+    let destination = |path: &Path| match &args.output_dir {
+        Some(dir) => Path::new(dir).join(path.strip_prefix(&base).unwrap_or(path)),
+        None => path.to_path_buf(),
+    };
+
+    let progress = MultiProgress::new();
+    let overall = progress.add(ProgressBar::new(paths.len() as u64));
+    overall.set_style(
+        ProgressStyle::default_bar()
+            .template("{bar:30.rgb(181,126,220)} {pos}/{len} files, {elapsed} elapsed")?,
+    );
+    let file_style = spinner_style()?;
+
+    let store = Store::open(&config.backups);
+    let (step, llm_backend, progress, store) =
+        (&step, llm_backend.as_ref(), &progress, store.as_ref());
+    let mut results = futures_util::stream::iter(paths.iter())
+        .map(|path| {
+            let destination = destination(path);
+            let spinner = start_spinner(
+                progress.insert_from_back(1, ProgressBar::new_spinner()),
+                file_style.clone(),
+                path.display().to_string(),
+            );
+            let (request, vars) = (request.clone(), vars.clone());
+            async move {
+                let result =
+                    optimize_file(llm_backend, step, request, vars, path, &destination, store)
+                        .await;
+                spinner.finish_and_clear();
+                progress.remove(&spinner);
+                (path, destination, result)
+            }
+        })
+        .buffer_unordered(args.jobs.into());
+
+    let mut reports: Vec<BatchReport> = Vec::new();
+    while let Some((path, destination, result)) = results.next().await {
+        overall.inc(1);
+        let failed = result.is_err();
+        if let Err(e) = &result {
+            progress.println(format!("{} {}: {}", style("✗").red(), path.display(), e))?;
+        }
+        reports.push(BatchReport {
+            path: path.clone(),
+            status: if failed {
+                BatchStatus::Failed
+            } else {
+                BatchStatus::Ok
+            },
+            output: result.as_ref().ok().map(|_| destination),
+            error: result.err().map(|e| e.to_string()),
+        });
+        if failed && args.fail_fast {
+            break;
+        }
+    }
+    drop(results);
+    overall.finish_and_clear();
+
+    for path in &paths {
+        if !reports.iter().any(|report| &report.path == path) {
+            reports.push(BatchReport {
+                path: path.clone(),
+                status: BatchStatus::Skipped,
+                output: None,
+                error: None,
+            });
+        }
+    }
+    let count = |status| reports.iter().filter(|r| r.status == status).count();
+    let (succeeded, failed, skipped) = (
+        count(BatchStatus::Ok),
+        count(BatchStatus::Failed),
+        count(BatchStatus::Skipped),
+    );
+
+    if format == OutputFormat::Text {
+        println!(
+            "{} succeeded, {} failed, {} skipped",
+            style(succeeded).green(),
+            style(failed).red(),
+            skipped
+        );
+        for report in reports.iter().filter(|r| r.status == BatchStatus::Failed) {
+            println!(
+                "  {}: {}",
+                report.path.display(),
+                report.error.as_deref().unwrap_or_default()
+            );
+        }
+    } else {
+        print_json_list(format, &reports)?;
+    }
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} files failed",
+            failed,
+            reports.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_dir() {
+        let paths = |list: &[&str]| list.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(
+            common_dir(&paths(&["docs/a.md", "docs/guides/b.md"])),
+            PathBuf::from("docs")
+        );
+        assert_eq!(
+            common_dir(&paths(&["docs/guides/b.md", "docs/a.md"])),
+            PathBuf::from("docs")
+        );
+        assert_eq!(common_dir(&paths(&["a.md", "docs/b.md"])), PathBuf::new());
+        assert_eq!(
+            common_dir(&paths(&["/srv/prompts/a.md"])),
+            PathBuf::from("/srv/prompts")
+        );
+    }
+}
@@ -0,0 +1,484 @@
+use crate::chunk::ChunkingOverrides;
+use crate::cli::ChatArgs;
+use crate::config::Config;
+use crate::core::{
+    BackendFactory, Step, build_prompt, print_stream, resolve_step, spinner, template_vars,
+    validate_model,
+};
+use crate::llm::r#trait::{GenerationParams, LlmBackend, LlmRequest, Message, Role};
+use anyhow::Result;
+use console::style;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// The commands of `chat`, typed at the prompt instead of a message.
+pub const HELP: &str = "\
+/save <path>              Write the last answer to a file
+/style <name>             Send the next message through another style
+/provider <name> [model]  Switch provider, keeping the conversation
+/undo                     Forget the last message and its answer
+/edit                     Edit the last answer in $EDITOR
+/help                     Show this list
+/exit                     Quit (or press Ctrl-D)";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Save(PathBuf),
+    Style(String),
+    Provider { name: String, model: Option<String> },
+    Undo,
+    Edit,
+    Help,
+    Exit,
+}
+
+/// Parses a line of input as a command. Returns `None` for a message.
+pub fn parse(line: &str) -> Option<Result<Command>> {
+    let line = line.trim();
+    let command = line.strip_prefix('/')?;
+    let mut words = command.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+    let usage = |usage: &str| Err(anyhow::anyhow!("Usage: {}", usage));
+    Some(match (name, args.as_slice()) {
+        ("save", [path]) => Ok(Command::Save(PathBuf::from(path))),
+        ("save", _) => usage("/save <path>"),
+        ("style", [style]) => Ok(Command::Style(style.to_string())),
+        ("style", _) => usage("/style <name>"),
+        ("provider", [name]) => Ok(Command::Provider {
+            name: name.to_string(),
+            model: None,
+        }),
+        ("provider", [name, model]) => Ok(Command::Provider {
+            name: name.to_string(),
+            model: Some(model.to_string()),
+        }),
+        ("provider", _) => usage("/provider <name> [model]"),
+        ("undo", []) => Ok(Command::Undo),
+        ("edit", []) => Ok(Command::Edit),
+        ("help", []) => Ok(Command::Help),
+        ("exit" | "quit", []) => Ok(Command::Exit),
+        _ => Err(anyhow::anyhow!(
+            "Unknown command '{}'. Type /help for the list of commands",
+            line
+        )),
+    })
+}
+
+/// The messages exchanged so far, as user and assistant turns.
+#[derive(Debug, Default)]
+pub struct Conversation {
+    messages: Vec<Message>,
+}
+
+impl Conversation {
+    pub fn history(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub fn push(&mut self, prompt: String, answer: String) {
+        self.messages.push(Message {
+            role: Role::User,
+            content: prompt,
+        });
+        self.messages.push(Message {
+            role: Role::Assistant,
+            content: answer,
+        });
+    }
+
+    /// Drops the last exchange. Returns false when there is none.
+    pub fn undo(&mut self) -> bool {
+        if self.messages.is_empty() {
+            return false;
+        }
+        self.messages.truncate(self.messages.len() - 2);
+        true
+    }
+
+    pub fn last_answer(&self) -> Option<&str> {
+        self.messages
+            .last()
+            .filter(|message| message.role == Role::Assistant)
+            .map(|message| message.content.as_str())
+    }
+
+    /// Replaces the last answer, so that follow-ups build on the edited text.
+    pub fn set_last_answer(&mut self, answer: String) {
+        if let Some(message) = self
+            .messages
+            .last_mut()
+            .filter(|message| message.role == Role::Assistant)
+        {
+            message.content = answer;
+        }
+    }
+}
+
+/// The state of a `chat` session that commands can change.
+struct ChatSession<'a> {
+    config: &'a Config,
+    create_backend: &'a BackendFactory,
+    style: Option<String>,
+    provider: Option<String>,
+    model: Option<String>,
+    step: Step,
+    llm_backend: Box<dyn LlmBackend>,
+    /// Whether the next message goes through the style rather than as is.
+    styled: bool,
+}
+
+impl ChatSession<'_> {
+    /// Resolves the style and provider again after a command changed them.
+    async fn switch(&mut self) -> Result<()> {
+        let step = resolve_step(
+            self.config,
+            self.style.as_deref(),
+            None,
+            self.provider.clone(),
+            self.model.as_ref(),
+            &GenerationParams::default(),
+            &ChunkingOverrides::default(),
+        )?;
+        let llm_backend = (self.create_backend)(&step.provider_name, &step.provider)?;
+        if let Some(model) = &self.model {
+            validate_model(llm_backend.as_ref(), &step.provider_name, model).await?;
+        }
+        self.step = step;
+        self.llm_backend = llm_backend;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "'{}' on {} ({})",
+            self.step.label, self.step.provider_name, self.step.provider.model
+        )
+    }
+}
+
+/// Sends a chat message with the conversation so far and prints the answer,
+/// streamed when the post-processing allows it.
+async fn send_chat_message(
+    session: &ChatSession<'_>,
+    conversation: &Conversation,
+    system_prompt: Option<String>,
+    prompt: String,
+) -> Result<String> {
+    let request = LlmRequest {
+        system_prompt,
+        history: conversation.history().to_vec(),
+        prompt,
+        params: session.step.params.clone(),
+        ..Default::default()
+    };
+    log::debug!("Using prompt:\n---\n{}\n---", request.prompt);
+    let postprocessor = &session.step.postprocessor;
+    let spinner = spinner("Thinking...".to_string())?;
+    let answer = if postprocessor.is_streamable() {
+        match session.llm_backend.stream(&request).await {
+            Ok(deltas) => print_stream(deltas, &spinner, postprocessor).await,
+            Err(e) => Err(e),
+        }
+    } else {
+        session
+            .llm_backend
+            .request(&request)
+            .await
+            .map(|response| response.text)
+    };
+    spinner.finish_and_clear();
+    let answer = postprocessor.apply(&answer?);
+    if !postprocessor.is_streamable() {
+        println!("{}", answer);
+    }
+    Ok(answer)
+}
+
+/// Refines a prompt over several turns. The first message goes through the
+/// style; follow-ups such as "shorter" are sent as they are, along with the
+/// conversation so far. Lines starting with `/` are commands.
+pub async fn run(
+    args: ChatArgs,
+    config: &Config,
+    create_backend: &BackendFactory,
+    input: &mut dyn BufRead,
+) -> Result<()> {
+    let step = resolve_step(
+        config,
+        args.style.as_deref(),
+        None,
+        args.provider.clone(),
+        args.model.as_ref(),
+        &GenerationParams::default(),
+        &ChunkingOverrides::default(),
+    )?;
+    let vars = template_vars(&[step.style_text.as_str()], None, args.vars)?;
+    let llm_backend = create_backend(&step.provider_name, &step.provider)?;
+    if let Some(model) = &args.model {
+        validate_model(llm_backend.as_ref(), &step.provider_name, model).await?;
+    }
+    let system_prompt = if args.no_system_prompt {
+        None
+    } else {
+        config.llm.system_prompt.clone()
+    };
+    let mut session = ChatSession {
+        config,
+        create_backend,
+        style: args.style,
+        provider: args.provider,
+        model: args.model,
+        step,
+        llm_backend,
+        styled: true,
+    };
+    let mut conversation = Conversation::default();
+
+    println!(
+        "Chatting with {}. Type /help for commands, /exit to quit.",
+        session.describe()
+    );
+    loop {
+        print!("{} ", style(">").bold());
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            println!();
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let result = match parse(line) {
+            None => {
+                let prompt = if session.styled {
+                    build_prompt(&session.step.style_text, line, vars.clone())
+                } else {
+                    Ok(line.to_string())
+                };
+                match prompt {
+                    Ok(prompt) => send_chat_message(
+                        &session,
+                        &conversation,
+                        system_prompt.clone(),
+                        prompt.clone(),
+                    )
+                    .await
+                    .map(|answer| {
+                        conversation.push(prompt, answer);
+                        session.styled = false;
+                    }),
+                    Err(e) => Err(e),
+                }
+            }
+            Some(Err(e)) => Err(e),
+            Some(Ok(Command::Exit)) => break,
+            Some(Ok(Command::Help)) => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            Some(Ok(Command::Undo)) => {
+                if conversation.undo() {
+                    session.styled |= conversation.history().is_empty();
+                    println!("Removed the last message and its answer.");
+                } else {
+                    println!("Nothing to undo.");
+                }
+                Ok(())
+            }
+            Some(Ok(Command::Save(path))) => match conversation.last_answer() {
+                Some(answer) => std::fs::write(&path, answer)
+                    .map(|()| println!("Saved to {}", path.display()))
+                    .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", path.display(), e)),
+                None => Err(anyhow::anyhow!("There is no answer to save yet")),
+            },
+            Some(Ok(Command::Edit)) => match conversation.last_answer() {
+                Some(answer) => crate::editor::edit_text(answer, &crate::editor::command(None))
+                    .map(|edited| {
+                        conversation.set_last_answer(edited);
+                        println!("Updated the last answer.");
+                    })
+                    .map_err(|e| anyhow::anyhow!("Failed to edit the answer: {}", e)),
+                None => Err(anyhow::anyhow!("There is no answer to edit yet")),
+            },
+            Some(Ok(Command::Style(name))) => {
+                let previous = session.style.replace(name);
+                let result = session.switch().await;
+                if result.is_err() {
+                    session.style = previous;
+                } else {
+                    session.styled = true;
+                    println!("The next message goes through {}.", session.describe());
+                }
+                result
+            }
+            Some(Ok(Command::Provider { name, model })) => {
+                let previous = (session.provider.replace(name), session.model.clone());
+                session.model = model;
+                let result = session.switch().await;
+                if result.is_err() {
+                    (session.provider, session.model) = previous;
+                } else {
+                    println!("Now chatting with {}.", session.describe());
+                }
+                result
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("{} {}", style("Error:").red(), e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Commands};
+    use crate::core::tests::ScriptedLlmBackend;
+    use clap::Parser;
+
+    #[test]
+    fn test_parse_commands() {
+        assert!(parse("make it shorter").is_none());
+        assert_eq!(
+            parse("/save out.md").unwrap().unwrap(),
+            Command::Save("out.md".into())
+        );
+        assert_eq!(
+            parse(" /provider ollama llama3.2 ").unwrap().unwrap(),
+            Command::Provider {
+                name: "ollama".to_string(),
+                model: Some("llama3.2".to_string()),
+            }
+        );
+        assert_eq!(parse("/quit").unwrap().unwrap(), Command::Exit);
+        assert_eq!(
+            parse("/style").unwrap().unwrap_err().to_string(),
+            "Usage: /style <name>"
+        );
+        assert_eq!(
+            parse("/retry now").unwrap().unwrap_err().to_string(),
+            "Unknown command '/retry now'. Type /help for the list of commands"
+        );
+    }
+
+    #[test]
+    fn test_conversation_undo_and_edit() {
+        let mut conversation = Conversation::default();
+        assert_eq!(conversation.last_answer(), None);
+        assert!(!conversation.undo());
+
+        conversation.push("Refine: a todo app".to_string(), "Long".to_string());
+        conversation.push("shorter".to_string(), "Short".to_string());
+        assert_eq!(conversation.last_answer(), Some("Short"));
+
+        conversation.set_last_answer("Edited".to_string());
+        assert_eq!(conversation.history()[3].content, "Edited");
+
+        assert!(conversation.undo());
+        assert_eq!(conversation.history().len(), 2);
+        assert_eq!(conversation.last_answer(), Some("Long"));
+    }
+
+    #[tokio::test]
+    async fn test_run_chat_keeps_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = dir.path().join("answer.md");
+        let backend = ScriptedLlmBackend::script(vec![
+            "A todo app with tags and due dates.",
+            "A todo app.",
+            "Todo app with tags.",
+        ]);
+        let create_backend = backend.factory();
+        let script = format!(
+            "a todo app\nshorter\n/undo\n/unknown\nsimpler\n/save {}\n/exit\nnever sent\n",
+            saved.display()
+        );
+        let Commands::Chat(args) =
+            Cli::parse_from(["inkspect", "chat", "--style", "code-gen"]).command
+        else {
+            unreachable!()
+        };
+
+        run(
+            args,
+            &Config::default(),
+            &create_backend,
+            &mut std::io::Cursor::new(script),
+        )
+        .await
+        .unwrap();
+
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].history.is_empty());
+        assert!(
+            requests[0]
+                .prompt
+                .starts_with("You are an expert AI programmer.")
+        );
+        assert!(requests[0].prompt.ends_with("User request:\na todo app"));
+        assert_eq!(requests[1].prompt, "shorter");
+        assert_eq!(
+            requests[1].history,
+            vec![
+                Message {
+                    role: Role::User,
+                    content: requests[0].prompt.clone(),
+                },
+                Message {
+                    role: Role::Assistant,
+                    content: "A todo app with tags and due dates.".to_string(),
+                },
+            ]
+        );
+        // The undone exchange is not sent again.
+        assert_eq!(requests[2].prompt, "simpler");
+        assert_eq!(requests[2].history, requests[1].history);
+        assert_eq!(
+            std::fs::read_to_string(&saved).unwrap(),
+            "Todo app with tags."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_chat_switches_style() {
+        let backend = ScriptedLlmBackend::default();
+        let create_backend = backend.factory();
+        let Commands::Chat(args) =
+            Cli::parse_from(["inkspect", "chat", "--style", "code-gen"]).command
+        else {
+            unreachable!()
+        };
+        let script = "a todo app\n/style missing\nagain\n/style code-spec\nnow as a spec\n";
+
+        run(
+            args,
+            &Config::default(),
+            &create_backend,
+            &mut std::io::Cursor::new(script),
+        )
+        .await
+        .unwrap();
+
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 3);
+        // An unknown style is reported and the session carries on.
+        assert_eq!(requests[1].prompt, "again");
+        assert!(
+            requests[2]
+                .prompt
+                .starts_with("You are a senior software architect.")
+        );
+        assert!(
+            requests[2]
+                .prompt
+                .ends_with("Feature request:\nnow as a spec")
+        );
+        assert_eq!(requests[2].history.len(), 4);
+    }
+}
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Token budget of a chunk when neither the style nor the command line sets one.
+pub const DEFAULT_MAX_TOKENS: usize = 4000;
+
+/// Where an input too large for one request may be cut.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkStrategy {
+    /// At Markdown headings, falling back to paragraphs for long sections.
+    #[default]
+    Headings,
+    /// At blank lines between paragraphs.
+    Paragraphs,
+}
+
+/// How a style processes inputs larger than `max_tokens`: the style runs on
+/// each chunk, then `reduce_prompt`, if set, merges the partial results.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Chunking {
+    #[serde(default)]
+    pub strategy: ChunkStrategy,
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reduce_prompt: Option<String>,
+}
+
+fn default_max_tokens() -> usize {
+    DEFAULT_MAX_TOKENS
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Self {
+            strategy: ChunkStrategy::default(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+            reduce_prompt: None,
+        }
+    }
+}
+
+/// The chunking flags of the command line. Any of them enables chunking,
+/// on top of the style's own settings if it has some.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkingOverrides {
+    pub strategy: Option<ChunkStrategy>,
+    pub max_tokens: Option<usize>,
+    pub reduce_prompt: Option<String>,
+}
+
+impl ChunkingOverrides {
+    pub fn apply(&self, style: Option<&Chunking>) -> Option<Chunking> {
+        let enabled =
+            self.strategy.is_some() || self.max_tokens.is_some() || self.reduce_prompt.is_some();
+        if style.is_none() && !enabled {
+            return None;
+        }
+        let mut chunking = style.cloned().unwrap_or_default();
+        if let Some(strategy) = self.strategy {
+            chunking.strategy = strategy;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            chunking.max_tokens = max_tokens;
+        }
+        if let Some(reduce_prompt) = &self.reduce_prompt {
+            chunking.reduce_prompt = Some(reduce_prompt.clone());
+        }
+        Some(chunking)
+    }
+}
+
+/// A rough token count: about four characters per token for English text
+/// and code, which is close enough to stay under a provider's limit.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Level {
+    Headings,
+    Paragraphs,
+    Lines,
+}
+
+fn is_heading(line: &str) -> bool {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    (1..=6).contains(&hashes) && line[hashes..].starts_with([' ', '\t', '\n', '\r'])
+}
+
+/// Splits `text` where a segment may start at `level`, never inside a code
+/// fence except at the line level. The ranges cover the whole text.
+fn segments(text: &str, level: Level) -> Vec<Range<usize>> {
+    let mut starts = vec![0];
+    let mut in_fence = false;
+    let mut previous_blank = false;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let is_blank = line.trim().is_empty();
+        let starts_here = match level {
+            Level::Headings => !in_fence && is_heading(line),
+            Level::Paragraphs => !in_fence && previous_blank && !is_blank,
+            Level::Lines => true,
+        };
+        if starts_here && offset > 0 {
+            starts.push(offset);
+        }
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        previous_blank = is_blank;
+        offset += line.len();
+    }
+    starts
+        .iter()
+        .zip(starts.iter().skip(1).chain([&text.len()]))
+        .map(|(start, end)| *start..*end)
+        .collect()
+}
+
+/// Cuts `text` every `max_tokens * 4` characters, for a single line that is
+/// too long on its own.
+fn hard_split(text: &str, max_tokens: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for (count, (index, _)) in text.char_indices().enumerate() {
+        if count > 0 && count % (max_tokens * 4) == 0 {
+            ranges.push(start..index);
+            start = index;
+        }
+    }
+    ranges.push(start..text.len());
+    ranges
+}
+
+/// Splits `text[range]` into pieces under `max_tokens`, using the coarsest
+/// level that works and merging neighbouring pieces while they fit.
+fn pack(text: &str, range: Range<usize>, levels: &[Level], max_tokens: usize) -> Vec<Range<usize>> {
+    let segment = &text[range.clone()];
+    if estimate_tokens(segment) <= max_tokens {
+        return vec![range];
+    }
+    let shift = |r: Range<usize>| range.start + r.start..range.start + r.end;
+    let pieces: Vec<Range<usize>> = match levels.split_first() {
+        Some((level, finer)) => segments(segment, *level)
+            .into_iter()
+            .flat_map(|r| pack(text, shift(r), finer, max_tokens))
+            .collect(),
+        None => hard_split(segment, max_tokens)
+            .into_iter()
+            .map(shift)
+            .collect(),
+    };
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for piece in pieces {
+        match merged.last_mut() {
+            Some(last) if estimate_tokens(&text[last.start..piece.end]) <= max_tokens => {
+                last.end = piece.end;
+            }
+            _ => merged.push(piece),
+        }
+    }
+    merged
+}
+
+/// Splits `text` into consecutive chunks of at most `max_tokens` estimated
+/// tokens each. Joining the chunks gives back the text.
+pub fn split(text: &str, strategy: ChunkStrategy, max_tokens: usize) -> Vec<&str> {
+    let levels: &[Level] = match strategy {
+        ChunkStrategy::Headings => &[Level::Headings, Level::Paragraphs, Level::Lines],
+        ChunkStrategy::Paragraphs => &[Level::Paragraphs, Level::Lines],
+    };
+    pack(text, 0..text.len(), levels, max_tokens.max(1))
+        .into_iter()
+        .map(|range| &text[range])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "# Design\n\nIntro paragraph.\n\n## Storage\n\nWe keep data in SQLite.\n\n```sh\n# not a heading\nsqlite3 db\n```\n\n## API\n\nREST endpoints.\n";
+
+    #[test]
+    fn test_small_input_is_one_chunk() {
+        assert_eq!(split(DOC, ChunkStrategy::Headings, 1000), vec![DOC]);
+        assert_eq!(split("", ChunkStrategy::Paragraphs, 10), vec![""]);
+    }
+
+    #[test]
+    fn test_split_at_headings() {
+        let chunks = split(DOC, ChunkStrategy::Headings, 20);
+        assert_eq!(
+            chunks,
+            vec![
+                "# Design\n\nIntro paragraph.\n\n",
+                "## Storage\n\nWe keep data in SQLite.\n\n```sh\n# not a heading\nsqlite3 db\n```\n\n",
+                "## API\n\nREST endpoints.\n",
+            ]
+        );
+        assert_eq!(chunks.concat(), DOC);
+    }
+
+    #[test]
+    fn test_split_at_paragraphs() {
+        let chunks = split(DOC, ChunkStrategy::Paragraphs, 12);
+        assert_eq!(chunks.concat(), DOC);
+        assert!(chunks.iter().all(|c| estimate_tokens(c) <= 12));
+        // The code block stays whole even though it contains a blank-free
+        // heading-like line.
+        assert!(chunks.contains(&"```sh\n# not a heading\nsqlite3 db\n```\n\n## API\n\n"));
+    }
+
+    #[test]
+    fn test_long_sections_fall_back_to_finer_cuts() {
+        let section = format!("# Big\n\n{}\n\n{}\n", "a".repeat(30), "b".repeat(30));
+        let chunks = split(&section, ChunkStrategy::Headings, 10);
+        // The heading stays with the first paragraph while they fit.
+        assert_eq!(
+            chunks,
+            vec![
+                format!("# Big\n\n{}\n\n", "a".repeat(30)),
+                format!("{}\n", "b".repeat(30)),
+            ]
+        );
+
+        let line = "é".repeat(100);
+        let chunks = split(&line, ChunkStrategy::Paragraphs, 10);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.concat(), line);
+    }
+
+    #[test]
+    fn test_overrides() {
+        assert_eq!(ChunkingOverrides::default().apply(None), None);
+
+        let style = Chunking {
+            strategy: ChunkStrategy::Paragraphs,
+            max_tokens: 2000,
+            reduce_prompt: Some("Merge.".to_string()),
+        };
+        assert_eq!(
+            ChunkingOverrides::default().apply(Some(&style)),
+            Some(style.clone())
+        );
+        let overrides = ChunkingOverrides {
+            max_tokens: Some(500),
+            ..Default::default()
+        };
+        assert_eq!(
+            overrides.apply(None),
+            Some(Chunking {
+                max_tokens: 500,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            overrides.apply(Some(&style)),
+            Some(Chunking {
+                max_tokens: 500,
+                ..style
+            })
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::chunk::ChunkStrategy;
+use crate::extract::OverwritePolicy;
 use clap::Parser;
 
 #[derive(Parser, Debug)]
@@ -15,11 +17,106 @@ pub struct Cli {
     #[arg(long)]
     pub show_secrets: bool,
 
+    /// How results are printed: plain text, or JSON for scripts
+    #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
     /// The command to execute
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// The layout of what inkspect prints on stdout.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable output.
+    #[default]
+    Text,
+    /// A single pretty-printed JSON document.
+    Json,
+    /// One compact JSON object per line.
+    Ndjson,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group(clap::ArgGroup::new("destination").required(true).args(["in_place", "output_dir"])))]
+pub struct BatchArgs {
+    /// Files, directories or globs to optimize
+    #[arg(required = true, value_name = "PATH")]
+    pub paths: Vec<String>,
+
+    /// The prompt style to use
+    #[arg(short, long)]
+    pub style: Option<String>,
+
+    /// The provider to use
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// The model to use, overriding the provider and style settings
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// A template variable for the style, as key=value; may be repeated
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
+    /// Replace each file with its optimized version
+    #[arg(long)]
+    pub in_place: bool,
+
+    /// Write the results under this directory, mirroring the input paths
+    #[arg(short, long, value_name = "DIR")]
+    pub output_dir: Option<String>,
+
+    /// How many files to process at the same time
+    #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: u16,
+
+    /// Stop at the first file that fails
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Disable the system prompt
+    #[arg(long)]
+    pub no_system_prompt: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ChatArgs {
+    /// The prompt style the first message goes through
+    #[arg(short, long)]
+    pub style: Option<String>,
+
+    /// The provider to use
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// The model to use, overriding the provider and style settings
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// A template variable for the style, as key=value; may be repeated
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
+    /// Disable the system prompt
+    #[arg(long)]
+    pub no_system_prompt: bool,
+}
+
+fn parse_var(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", arg))?;
+    if key.trim().is_empty() {
+        return Err(format!("missing variable name in '{}'", arg));
+    }
+    Ok((key.trim().to_string(), value.to_string()))
+}
+
+// Parsed once at startup, so the size of the optimize variant does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Parser, Debug)]
 pub enum Commands {
     /// Optimize a prompt
@@ -28,10 +125,15 @@ pub enum Commands {
         #[arg(short, long)]
         input: Option<String>,
 
-        /// Path to a file to use as input
+        /// Path to a file to use as input, or `-` for stdin
         #[arg(short, long)]
         file: Option<String>,
 
+        /// Read the input from stdin. Implied when stdin is not a terminal
+        /// and no other input is given
+        #[arg(long)]
+        stdin: bool,
+
         /// The editor to use for input
         #[arg(short, long)]
         editor: Option<String>,
@@ -40,6 +142,10 @@ pub enum Commands {
         #[arg(short, long)]
         provider: Option<String>,
 
+        /// The model to use, overriding the provider and style settings
+        #[arg(short, long)]
+        model: Option<String>,
+
         /// The style to use
         #[arg(short, long)]
         style: Option<String>,
@@ -48,6 +154,38 @@ pub enum Commands {
         #[arg(long)]
         prompt: Option<String>,
 
+        /// Run a pipeline from the configuration instead of a single style
+        #[arg(long, conflicts_with_all = ["style", "prompt"])]
+        pipeline: Option<String>,
+
+        /// Save the output of every pipeline step to this directory
+        #[arg(long, value_name = "DIR", requires = "pipeline")]
+        keep_intermediate: Option<String>,
+
+        /// A template variable for the style, as key=value; may be repeated
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+
+        /// A file, directory or glob to attach as context; may be repeated
+        #[arg(short, long = "context", value_name = "PATH")]
+        context: Vec<String>,
+
+        /// Maximum combined size of the context files, in bytes
+        #[arg(long, value_name = "BYTES", default_value_t = crate::context::DEFAULT_MAX_BYTES)]
+        max_context_bytes: u64,
+
+        /// Split inputs over the chunk budget and run the style on each part
+        #[arg(long, value_enum, value_name = "STRATEGY")]
+        chunk: Option<ChunkStrategy>,
+
+        /// Estimated token budget of a chunk [default: 4000]
+        #[arg(long, value_name = "TOKENS", value_parser = clap::value_parser!(u32).range(1..))]
+        chunk_tokens: Option<u32>,
+
+        /// Prompt that merges the results of the chunks into one response
+        #[arg(long, value_name = "PROMPT")]
+        reduce_prompt: Option<String>,
+
         /// The output file
         #[arg(short, long)]
         output: Option<String>,
@@ -56,9 +194,75 @@ pub enum Commands {
         #[arg(long)]
         in_place: bool,
 
+        /// Edit the response in the editor before it is written; an empty buffer aborts
+        #[arg(long, conflicts_with = "extract_to")]
+        review: bool,
+
+        /// Show a coloured diff of the changes to the file being written
+        #[arg(long)]
+        diff: bool,
+
+        /// Show the diff and ask before writing: y(es), n(o) or e(dit)
+        #[arg(long)]
+        confirm: bool,
+
+        /// Write the changes to the input file as a patch instead of applying them
+        #[arg(long, value_name = "PATH", requires = "file", conflicts_with_all = ["output", "in_place", "extract_to", "diff", "confirm"])]
+        patch: Option<String>,
+
+        /// Write the fenced code blocks of the response as files in this directory
+        #[arg(long, value_name = "DIR", conflicts_with = "in_place")]
+        extract_to: Option<String>,
+
+        /// List the files --extract-to would write without writing them
+        #[arg(long, requires = "extract_to")]
+        dry_run: bool,
+
+        /// What --extract-to does with files that already exist [default: fail]
+        #[arg(long, value_enum, requires = "extract_to")]
+        overwrite: Option<OverwritePolicy>,
+
         /// Disable the system prompt
         #[arg(long)]
         no_system_prompt: bool,
+
+        /// Sampling temperature, overriding the style and provider settings
+        #[arg(long)]
+        temperature: Option<f32>,
+
+        /// Nucleus sampling threshold, overriding the style and provider settings
+        #[arg(long)]
+        top_p: Option<f32>,
+
+        /// Maximum number of tokens to generate
+        #[arg(long)]
+        max_output_tokens: Option<u32>,
+
+        /// A sequence that stops generation; may be repeated
+        #[arg(long = "stop", allow_hyphen_values = true)]
+        stop_sequences: Vec<String>,
+
+        /// Sampling seed, for providers that support it
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Optimize many files with the same style, several at a time
+    Batch(BatchArgs),
+    /// Refine a prompt over several turns, keeping the conversation
+    Chat(ChatArgs),
+    /// Restore a file to what it held before inkspect last overwrote it
+    Undo {
+        /// The file to restore; defaults to the last file inkspect overwrote
+        file: Option<String>,
+
+        /// The version to restore, as numbered by `versions`
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+        to: u64,
+    },
+    /// List the saved versions of a file, newest first
+    Versions {
+        /// The file to list the versions of
+        file: String,
     },
     /// List available models from a provider
     ListModels {
@@ -111,6 +315,217 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_parsing_context() {
+        let args = vec![
+            "inkspect",
+            "optimize",
+            "--context",
+            "src/*.rs",
+            "-c",
+            "Cargo.toml",
+            "--max-context-bytes",
+            "1000",
+        ];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Optimize {
+                context,
+                max_context_bytes,
+                ..
+            } => {
+                assert_eq!(context, vec!["src/*.rs", "Cargo.toml"]);
+                assert_eq!(max_context_bytes, 1000);
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_chunking() {
+        let args = vec![
+            "inkspect",
+            "optimize",
+            "--chunk",
+            "paragraphs",
+            "--chunk-tokens",
+            "2000",
+            "--reduce-prompt",
+            "Merge these summaries.",
+        ];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Optimize {
+                chunk,
+                chunk_tokens,
+                reduce_prompt,
+                ..
+            } => {
+                assert_eq!(chunk, Some(ChunkStrategy::Paragraphs));
+                assert_eq!(chunk_tokens, Some(2000));
+                assert_eq!(reduce_prompt.as_deref(), Some("Merge these summaries."));
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+        assert!(Cli::try_parse_from(["inkspect", "optimize", "--chunk-tokens", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_review() {
+        let cli = Cli::parse_from([
+            "inkspect",
+            "optimize",
+            "--file",
+            "a.md",
+            "--in-place",
+            "--review",
+        ]);
+        match cli.command {
+            Commands::Optimize {
+                review, in_place, ..
+            } => {
+                assert!(review);
+                assert!(in_place);
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+        assert!(
+            Cli::try_parse_from(["inkspect", "optimize", "--review", "--extract-to", "src"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_parsing_diff_confirm_patch() {
+        let cli = Cli::parse_from([
+            "inkspect",
+            "optimize",
+            "--file",
+            "a.md",
+            "--in-place",
+            "--diff",
+            "--confirm",
+        ]);
+        match cli.command {
+            Commands::Optimize {
+                diff,
+                confirm,
+                patch,
+                ..
+            } => {
+                assert!(diff);
+                assert!(confirm);
+                assert_eq!(patch, None);
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+        let cli = Cli::parse_from([
+            "inkspect", "optimize", "--file", "a.md", "--patch", "a.patch",
+        ]);
+        match cli.command {
+            Commands::Optimize { patch, .. } => assert_eq!(patch.as_deref(), Some("a.patch")),
+            _ => panic!("Expected Optimize command"),
+        }
+        assert!(Cli::try_parse_from(["inkspect", "optimize", "--patch", "a.patch"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "inkspect",
+                "optimize",
+                "--file",
+                "a.md",
+                "--in-place",
+                "--patch",
+                "a.patch"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_parsing_undo_and_versions() {
+        match Cli::parse_from(["inkspect", "undo"]).command {
+            Commands::Undo { file, to } => {
+                assert_eq!(file, None);
+                assert_eq!(to, 1);
+            }
+            _ => panic!("Expected Undo command"),
+        }
+        match Cli::parse_from(["inkspect", "undo", "notes.md"]).command {
+            Commands::Undo { file, .. } => assert_eq!(file.as_deref(), Some("notes.md")),
+            _ => panic!("Expected Undo command"),
+        }
+        match Cli::parse_from(["inkspect", "undo", "notes.md", "--to", "3"]).command {
+            Commands::Undo { to, .. } => assert_eq!(to, 3),
+            _ => panic!("Expected Undo command"),
+        }
+        assert!(Cli::try_parse_from(["inkspect", "undo", "--to", "0"]).is_err());
+        match Cli::parse_from(["inkspect", "versions", "notes.md"]).command {
+            Commands::Versions { file } => assert_eq!(file, "notes.md"),
+            _ => panic!("Expected Versions command"),
+        }
+        assert!(Cli::try_parse_from(["inkspect", "versions"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_chat() {
+        let cli = Cli::parse_from(["inkspect", "chat", "--style", "refine", "-p", "claude"]);
+        match cli.command {
+            Commands::Chat(args) => {
+                assert_eq!(args.style.as_deref(), Some("refine"));
+                assert_eq!(args.provider.as_deref(), Some("claude"));
+                assert_eq!(args.model, None);
+            }
+            _ => panic!("Expected Chat command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_batch() {
+        let args = vec![
+            "inkspect",
+            "batch",
+            "--style",
+            "refine",
+            "-j",
+            "8",
+            "--in-place",
+            "a.md",
+            "docs/*.md",
+        ];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Batch(args) => {
+                assert_eq!(args.paths, vec!["a.md", "docs/*.md"]);
+                assert_eq!(args.style, Some("refine".to_string()));
+                assert_eq!(args.jobs, 8);
+                assert!(args.in_place);
+                assert!(!args.fail_fast);
+            }
+            _ => panic!("Expected Batch command"),
+        }
+
+        // A destination is required, and only one.
+        assert!(Cli::try_parse_from(vec!["inkspect", "batch", "a.md"]).is_err());
+        assert!(
+            Cli::try_parse_from(vec!["inkspect", "batch", "--in-place", "-o", "out", "a.md"])
+                .is_err()
+        );
+        assert!(
+            Cli::try_parse_from(vec!["inkspect", "batch", "--in-place", "-j", "0", "a.md"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_parsing_stdin() {
+        let args = vec!["inkspect", "optimize", "--stdin"];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Optimize { stdin, .. } => assert!(stdin),
+            _ => panic!("Expected Optimize command"),
+        }
+    }
+
     #[test]
     fn test_cli_parsing_provider() {
         let args = vec!["inkspect", "optimize", "--provider", "gemini"];
@@ -123,6 +538,122 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_parsing_model() {
+        let args = vec!["inkspect", "optimize", "--model", "claude-opus-4-20250514"];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Optimize { model, .. } => {
+                assert_eq!(model, Some("claude-opus-4-20250514".to_string()));
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parsing_vars() {
+        let args = vec![
+            "inkspect",
+            "optimize",
+            "--var",
+            "language=Rust",
+            "--var",
+            "goal=a=b",
+        ];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Optimize { vars, .. } => {
+                assert_eq!(
+                    vars,
+                    vec![
+                        ("language".to_string(), "Rust".to_string()),
+                        ("goal".to_string(), "a=b".to_string())
+                    ]
+                );
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+        assert!(Cli::try_parse_from(vec!["inkspect", "optimize", "--var", "novalue"]).is_err());
+        assert!(Cli::try_parse_from(vec!["inkspect", "optimize", "--var", "=x"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parsing_pipeline() {
+        let args = vec![
+            "inkspect",
+            "optimize",
+            "--pipeline",
+            "spec",
+            "--keep-intermediate",
+            "steps",
+        ];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Optimize {
+                pipeline,
+                keep_intermediate,
+                ..
+            } => {
+                assert_eq!(pipeline, Some("spec".to_string()));
+                assert_eq!(keep_intermediate, Some("steps".to_string()));
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+        assert!(
+            Cli::try_parse_from(vec![
+                "inkspect",
+                "optimize",
+                "--pipeline",
+                "a",
+                "--style",
+                "b"
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from(vec!["inkspect", "optimize", "--keep-intermediate", "dir"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_parsing_format() {
+        let cli = Cli::parse_from(vec!["inkspect", "list-models", "--format", "ndjson"]);
+        assert_eq!(cli.format, OutputFormat::Ndjson);
+        let cli = Cli::parse_from(vec!["inkspect", "--format", "json", "list-prompts"]);
+        assert_eq!(cli.format, OutputFormat::Json);
+        let cli = Cli::parse_from(vec!["inkspect", "list-prompts"]);
+        assert_eq!(cli.format, OutputFormat::Text);
+    }
+
+    #[test]
+    fn test_cli_parsing_extract_to() {
+        let args = vec![
+            "inkspect",
+            "optimize",
+            "--extract-to",
+            "out",
+            "--dry-run",
+            "--overwrite",
+            "skip",
+        ];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Optimize {
+                extract_to,
+                dry_run,
+                overwrite,
+                ..
+            } => {
+                assert_eq!(extract_to, Some("out".to_string()));
+                assert!(dry_run);
+                assert_eq!(overwrite, Some(OverwritePolicy::Skip));
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+        assert!(Cli::try_parse_from(vec!["inkspect", "optimize", "--dry-run"]).is_err());
+    }
+
     #[test]
     fn test_cli_parsing_style() {
         let args = vec!["inkspect", "optimize", "--style", "refine"];
@@ -197,4 +728,38 @@ mod tests {
             _ => panic!("Expected Optimize command"),
         }
     }
+
+    #[test]
+    fn test_cli_parsing_generation_params() {
+        let args = vec![
+            "inkspect",
+            "optimize",
+            "--temperature",
+            "0.3",
+            "--max-output-tokens",
+            "2048",
+            "--stop",
+            "END",
+            "--stop",
+            "---",
+        ];
+        let cli = Cli::parse_from(args);
+        match cli.command {
+            Commands::Optimize {
+                temperature,
+                top_p,
+                max_output_tokens,
+                stop_sequences,
+                seed,
+                ..
+            } => {
+                assert_eq!(temperature, Some(0.3));
+                assert_eq!(top_p, None);
+                assert_eq!(max_output_tokens, Some(2048));
+                assert_eq!(stop_sequences, vec!["END", "---"]);
+                assert_eq!(seed, None);
+            }
+            _ => panic!("Expected Optimize command"),
+        }
+    }
 }
@@ -1,26 +1,122 @@
+use crate::backup::BackupConfig;
+use crate::chunk::Chunking;
+use crate::editor::EditorConfig;
+use crate::llm::r#trait::GenerationParams;
+use crate::postprocess::{self, Processor};
+use crate::schema::SchemaSource;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Prompt {
     pub name: String,
     pub prompt: String,
     pub description: Option<String>,
+    /// Provider this style is sent to unless `--provider` is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Model this style uses on its provider unless `--model` is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Post-processing for this style, replacing the global `llm.postprocess`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postprocess: Option<Vec<Processor>>,
+    /// JSON Schema the response must match, inline or as a path to a file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<SchemaSource>,
+    /// How many times a response failing the schema is sent back for fixing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_retries: Option<u32>,
+    /// Splitting of inputs too large for one request, with the reduce prompt
+    /// that merges the results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunking: Option<Chunking>,
+    /// Generation settings for this style, overriding the provider's.
+    #[serde(flatten)]
+    pub params: GenerationParams,
+}
+
+/// One step of a pipeline: the style to apply to the previous step's output.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PipelineStep {
+    pub style: String,
+    /// Provider for this step, overriding the style's binding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+}
+
+/// A named chain of styles run one after the other by `optimize --pipeline`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Pipeline {
+    pub description: Option<String>,
+    pub steps: Vec<PipelineStep>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub llm: Llm,
-    pub providers: Providers,
+    pub providers: BTreeMap<String, Provider>,
     pub prompts: Vec<Prompt>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pipelines: BTreeMap<String, Pipeline>,
+    #[serde(default)]
+    pub backups: BackupConfig,
+    #[serde(default)]
+    pub editor: EditorConfig,
 }
 
 impl Config {
     pub fn sanitized(&self) -> Self {
         let mut sanitized_config = self.clone();
-        sanitized_config.providers.gemini.api_key = "[REDACTED]".to_string();
-        sanitized_config.providers.claude.api_key = "[REDACTED]".to_string();
+        for provider in sanitized_config.providers.values_mut() {
+            // Keyless providers such as Ollama have nothing to hide.
+            if !provider.api_key.is_empty() {
+                provider.api_key = "[REDACTED]".to_string();
+            }
+        }
         sanitized_config
     }
+
+    pub fn pipeline(&self, name: &str) -> anyhow::Result<&Pipeline> {
+        let pipeline = self.pipelines.get(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Pipeline '{}' not found in configuration. Available pipelines: {}",
+                name,
+                if self.pipelines.is_empty() {
+                    "none".to_string()
+                } else {
+                    self.pipelines
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+            )
+        })?;
+        if pipeline.steps.is_empty() {
+            return Err(anyhow::anyhow!("Pipeline '{}' has no steps.", name));
+        }
+        Ok(pipeline)
+    }
+
+    /// Looks up a provider entry by name. The built-in providers remain
+    /// available under their own name even when the config file predates them.
+    pub fn provider(&self, name: &str) -> anyhow::Result<Provider> {
+        if let Some(provider) = self.providers.get(name) {
+            return Ok(provider.clone());
+        }
+        let mut builtin = Config::default().providers;
+        builtin.remove(name).ok_or_else(|| {
+            let mut names: Vec<&String> = self.providers.keys().chain(builtin.keys()).collect();
+            names.sort();
+            names.dedup();
+            anyhow::anyhow!(
+                "Provider '{}' not found in configuration. Available providers: {}",
+                name,
+                names.into_iter().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -28,18 +124,68 @@ pub struct Llm {
     pub provider: String,
     pub default_prompt: String,
     pub system_prompt: Option<String>,
+    /// Processors applied to every response, unless the style sets its own.
+    #[serde(default = "postprocess::default_processors")]
+    pub postprocess: Vec<Processor>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Providers {
-    pub gemini: Provider,
-    pub claude: Provider,
+/// The API a provider entry speaks.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Gemini,
+    Claude,
+    Openai,
+    Ollama,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl ProviderKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gemini" => Some(Self::Gemini),
+            "claude" => Some(Self::Claude),
+            "openai" => Some(Self::Openai),
+            "ollama" => Some(Self::Ollama),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Provider {
+    /// Which API this entry speaks. May be omitted when the entry is named
+    /// after its kind, as in `[providers.claude]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ProviderKind>,
+    #[serde(default)]
     pub api_key: String,
     pub model: String,
+    /// Base URL of the API, also accepted as `endpoint`. Defaults to the
+    /// provider's public API; for Ollama this is the host the server listens on.
+    #[serde(default, alias = "endpoint", skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Total attempts for a request, retries on transient failures included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_attempts: Option<u32>,
+    /// Time budget in seconds after which no further retry is attempted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_deadline_secs: Option<u64>,
+    /// Default generation settings for requests sent to this provider.
+    #[serde(flatten)]
+    pub params: GenerationParams,
+}
+
+impl Provider {
+    pub fn kind(&self, name: &str) -> anyhow::Result<ProviderKind> {
+        self.kind
+            .or_else(|| ProviderKind::from_name(name))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Provider '{}' has no kind. Set `kind` to one of: gemini, claude, openai, ollama",
+                    name
+                )
+            })
+    }
 }
 
 impl Default for Config {
@@ -49,34 +195,71 @@ impl Default for Config {
                 provider: "claude".to_string(),
                 default_prompt: "code-spec".to_string(),
                 system_prompt: Some("You are an expert providing a direct and comprehensive answer. Your response should be direct, containing only the answer itself without any introductory remarks, conversational filler, or concluding statements. Do not add a summary or any closing comments. Get straight to the point.".to_string()),
+                postprocess: postprocess::default_processors(),
             },
-            providers: Providers {
-                gemini: Provider {
-                    api_key: "GEMINI_API_KEY".to_string(),
-                    model: "models/gemini-1.5-flash-latest".to_string(),
-                },
-                claude: Provider {
-                    api_key: "CLAUDE_API_KEY".to_string(),
-                    model: "claude-2".to_string(),
-                },
-            },
+            providers: BTreeMap::from([
+                (
+                    "gemini".to_string(),
+                    Provider {
+                        kind: Some(ProviderKind::Gemini),
+                        api_key: "GEMINI_API_KEY".to_string(),
+                        model: "models/gemini-1.5-flash-latest".to_string(),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "claude".to_string(),
+                    Provider {
+                        kind: Some(ProviderKind::Claude),
+                        api_key: "CLAUDE_API_KEY".to_string(),
+                        model: "claude-sonnet-4-20250514".to_string(),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "openai".to_string(),
+                    Provider {
+                        kind: Some(ProviderKind::Openai),
+                        api_key: String::new(),
+                        model: "gpt-4o-mini".to_string(),
+                        base_url: Some(crate::llm::openai::DEFAULT_BASE_URL.to_string()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "ollama".to_string(),
+                    Provider {
+                        kind: Some(ProviderKind::Ollama),
+                        api_key: String::new(),
+                        model: "llama3.2".to_string(),
+                        base_url: Some(crate::llm::ollama::DEFAULT_HOST.to_string()),
+                        ..Default::default()
+                    },
+                ),
+            ]),
             prompts: vec![
                 Prompt {
                     name: "code-spec".to_string(),
-                    prompt: "You are a senior software architect. Your task is to create a detailed specification for an AI coding agent. Do not write any code. Your output must be a Markdown document that guides the agent. The specification must enforce a strict Test-Driven Development (TDD) methodology. The document must include: 1. High-Level Goal, 2. Key Features, 3. Proposed Architecture & File Structure, 4. Data Structures & Types, 5. Step-by-Step TDD Implementation Plan (for each feature, specify the failing test to write first, then the implementation), 6. Error Handling, and 7. Testing Strategy (emphasizing unit tests for every feature). Your sole output is this specification document. Do not, under any circumstances, write the implementation code for the project. Your response must not contain any code.".to_string(),
+                    prompt: "You are a senior software architect. Your task is to create a detailed specification for an AI coding agent. Do not write any code. Your output must be a Markdown document that guides the agent. The specification must enforce a strict Test-Driven Development (TDD) methodology. The document must include: 1. High-Level Goal, 2. Key Features, 3. Proposed Architecture & File Structure, 4. Data Structures & Types, 5. Step-by-Step TDD Implementation Plan (for each feature, specify the failing test to write first, then the implementation), 6. Error Handling, and 7. Testing Strategy (emphasizing unit tests for every feature). Your sole output is this specification document. Do not, under any circumstances, write the implementation code for the project. Your response must not contain any code.\n\nFeature request:\n{{input}}".to_string(),
                     description: Some("Generate a specification for an AI coding agent".to_string()),
+                    ..Default::default()
                 },
                 Prompt {
                     name: "code-gen".to_string(),
-                    prompt: "You are an expert AI programmer. Your task is to generate a complete, production-quality, single-file application based on the user's request. The code must be well-commented, robust, and follow best practices. Include a section on how to build and run the application. Your output should be a single Markdown file containing the code and instructions.".to_string(),
+                    prompt: "You are an expert AI programmer. Your task is to generate a complete, production-quality, single-file application based on the user's request. The code must be well-commented, robust, and follow best practices. Include a section on how to build and run the application. Your output should be a single Markdown file containing the code and instructions.\n\nUser request:\n{{input}}".to_string(),
                     description: Some("Generate a complete, production-quality, single-file application".to_string()),
+                    ..Default::default()
                 },
                 Prompt {
                     name: "code-debug".to_string(),
-                    prompt: "You are an expert in debugging software. Your task is to craft a clean and effective prompt for a coding AI agent to help a developer solve a bug. Based on the user's bug description, generate a prompt for the AI agent that instructs it to perform the following tasks: 1. **Diagnose Potential Causes:** Systematically list the most likely reasons for the described bug. 2. **Propose Fixes:** For each potential cause, suggest a concrete fix, code change, or command to verify the issue. 3. **Explain the Problem:** Provide a clear and concise explanation of the likely root cause of the bug. The final output should be only the generated prompt, ready to be copied and given to the coding AI agent.".to_string(),
+                    prompt: "You are an expert in debugging software. Your task is to craft a clean and effective prompt for a coding AI agent to help a developer solve a bug. Based on the user's bug description, generate a prompt for the AI agent that instructs it to perform the following tasks: 1. **Diagnose Potential Causes:** Systematically list the most likely reasons for the described bug. 2. **Propose Fixes:** For each potential cause, suggest a concrete fix, code change, or command to verify the issue. 3. **Explain the Problem:** Provide a clear and concise explanation of the likely root cause of the bug. The final output should be only the generated prompt, ready to be copied and given to the coding AI agent.\n\nBug description:\n{{input}}".to_string(),
                     description: Some("Craft a prompt for a coding AI agent to debug a generic bug".to_string()),
+                    ..Default::default()
                 },
             ],
+            pipelines: BTreeMap::new(),
+            backups: BackupConfig::default(),
+            editor: EditorConfig::default(),
         }
     }
 }
@@ -127,13 +310,14 @@ mod tests {
                 },
                 claude: TestProvider {
                     api_key: "CLAUDE_API_KEY".to_string(),
-                    model: "claude-2".to_string(),
+                    model: "claude-sonnet-4-20250514".to_string(),
                 },
             },
             prompts: vec![Prompt {
                 name: "refine".to_string(),
                 prompt: "Refine this prompt".to_string(),
                 description: Some("Refine the prompt".to_string()),
+                ..Default::default()
             }],
         };
         let toml = toml::to_string(&config).unwrap();
@@ -142,9 +326,76 @@ mod tests {
         let loaded_config: Config = confy::load_path(file.path()).unwrap();
         assert_eq!(loaded_config.llm.provider, "gemini");
         assert_eq!(loaded_config.llm.default_prompt, "refine");
-        assert_eq!(loaded_config.providers.gemini.api_key, "GEMINI_API_KEY");
-        assert_eq!(loaded_config.providers.claude.api_key, "CLAUDE_API_KEY");
+        assert_eq!(loaded_config.providers["gemini"].api_key, "GEMINI_API_KEY");
+        assert_eq!(loaded_config.providers["claude"].api_key, "CLAUDE_API_KEY");
+        assert_eq!(
+            loaded_config.providers["claude"].kind("claude").unwrap(),
+            ProviderKind::Claude
+        );
         assert_eq!(loaded_config.prompts.first().unwrap().name, "refine");
+        // Configs written before post-processing existed keep stripping chatter.
+        assert_eq!(
+            loaded_config.llm.postprocess,
+            postprocess::default_processors()
+        );
+        // Configs written before the openai provider existed still load.
+        assert_eq!(
+            loaded_config
+                .provider("openai")
+                .unwrap()
+                .base_url
+                .as_deref(),
+            Some("https://api.openai.com/v1")
+        );
+    }
+
+    #[test]
+    fn test_load_openai_provider() {
+        let mut file = NamedTempFile::new().unwrap();
+        let toml = r#"
+[llm]
+provider = "openai"
+default_prompt = "code-spec"
+
+[providers.gemini]
+api_key = "GEMINI_API_KEY"
+model = "gemini-pro"
+
+[providers.claude]
+api_key = "CLAUDE_API_KEY"
+model = "claude-sonnet-4-20250514"
+
+[providers.openai]
+base_url = "http://localhost:8000/v1"
+model = "Qwen/Qwen2.5-Coder-7B-Instruct"
+
+[[prompts]]
+name = "code-spec"
+prompt = "Write a spec"
+"#;
+        file.write_all(toml.as_bytes()).unwrap();
+
+        let loaded_config: Config = confy::load_path(file.path()).unwrap();
+        let openai = &loaded_config.providers["openai"];
+        assert_eq!(openai.base_url.as_deref(), Some("http://localhost:8000/v1"));
+        assert_eq!(openai.api_key, "");
+        assert_eq!(openai.model, "Qwen/Qwen2.5-Coder-7B-Instruct");
+        assert_eq!(loaded_config.sanitized().providers["openai"].api_key, "");
+    }
+
+    #[test]
+    fn test_sanitized_skips_keyless_providers() {
+        let mut config = Config::default();
+        config.providers.get_mut("ollama").unwrap().base_url =
+            Some("http://gpu-box:11434".to_string());
+        let sanitized = config.sanitized();
+        assert_eq!(sanitized.providers["claude"].api_key, "[REDACTED]");
+        assert_eq!(sanitized.providers["gemini"].api_key, "[REDACTED]");
+        assert_eq!(sanitized.providers["ollama"].api_key, "");
+        assert_eq!(
+            sanitized.providers["ollama"].base_url.as_deref(),
+            Some("http://gpu-box:11434")
+        );
     }
 
     #[test]
@@ -155,4 +406,237 @@ mod tests {
         assert_eq!(config.llm.provider, "claude");
         assert_eq!(config.llm.default_prompt, "code-spec");
     }
+
+    #[test]
+    fn test_load_named_providers() {
+        let mut file = NamedTempFile::new().unwrap();
+        let toml = r#"
+[llm]
+provider = "work-claude"
+default_prompt = "code-spec"
+
+[providers.work-claude]
+kind = "claude"
+api_key = "WORK_KEY"
+model = "claude-opus-4-20250514"
+
+[providers.personal-claude]
+kind = "claude"
+api_key = "PERSONAL_KEY"
+model = "claude-3-5-haiku-latest"
+
+[providers.lab]
+kind = "openai"
+endpoint = "http://lab:8000/v1"
+model = "llama-3-70b"
+max_attempts = 5
+retry_deadline_secs = 30
+
+[providers.mystery]
+model = "unknown"
+
+[[prompts]]
+name = "code-spec"
+prompt = "Write a spec"
+"#;
+        file.write_all(toml.as_bytes()).unwrap();
+
+        let config: Config = confy::load_path(file.path()).unwrap();
+        let work = config.provider("work-claude").unwrap();
+        assert_eq!(work.kind("work-claude").unwrap(), ProviderKind::Claude);
+        assert_eq!(work.api_key, "WORK_KEY");
+        let personal = config.provider("personal-claude").unwrap();
+        assert_eq!(personal.model, "claude-3-5-haiku-latest");
+        let lab = config.provider("lab").unwrap();
+        assert_eq!(lab.kind("lab").unwrap(), ProviderKind::Openai);
+        assert_eq!(lab.base_url.as_deref(), Some("http://lab:8000/v1"));
+        assert_eq!(lab.max_attempts, Some(5));
+        assert_eq!(lab.retry_deadline_secs, Some(30));
+
+        let err = config.provider("mystery").unwrap().kind("mystery");
+        assert!(err.unwrap_err().to_string().contains("has no kind"));
+
+        let err = config.provider("nope").unwrap_err().to_string();
+        assert!(err.contains("Provider 'nope' not found"));
+        assert!(err.contains("personal-claude"));
+    }
+
+    #[test]
+    fn test_load_generation_params() {
+        let mut file = NamedTempFile::new().unwrap();
+        let toml = r#"
+[llm]
+provider = "claude"
+default_prompt = "code-spec"
+
+[providers.claude]
+api_key = "CLAUDE_API_KEY"
+model = "claude-sonnet-4-20250514"
+temperature = 0.7
+max_output_tokens = 8192
+stop_sequences = ["---"]
+
+[[prompts]]
+name = "code-spec"
+prompt = "Write a spec"
+temperature = 0.2
+seed = 7
+"#;
+        file.write_all(toml.as_bytes()).unwrap();
+
+        let config: Config = confy::load_path(file.path()).unwrap();
+        let claude = config.provider("claude").unwrap();
+        assert_eq!(claude.params.temperature, Some(0.7));
+        assert_eq!(claude.params.max_output_tokens, Some(8192));
+        assert_eq!(claude.params.stop_sequences, Some(vec!["---".to_string()]));
+        let style = &config.prompts[0].params;
+        assert_eq!(style.temperature, Some(0.2));
+        assert_eq!(style.seed, Some(7));
+        assert_eq!(style.max_output_tokens, None);
+
+        // Unset params are not written back out.
+        let written = toml::to_string(&Config::default()).unwrap();
+        assert!(!written.contains("temperature"));
+    }
+
+    #[test]
+    fn test_load_pipelines() {
+        let mut file = NamedTempFile::new().unwrap();
+        let toml = r#"
+[llm]
+provider = "claude"
+default_prompt = "code-spec"
+
+[providers.claude]
+api_key = "CLAUDE_API_KEY"
+model = "claude-sonnet-4-20250514"
+
+[[prompts]]
+name = "code-spec"
+prompt = "Write a spec"
+
+[pipelines.spec]
+description = "Refine, specify, review"
+steps = [
+    { style = "refine", provider = "ollama" },
+    { style = "code-spec" },
+]
+
+[pipelines.empty]
+steps = []
+"#;
+        file.write_all(toml.as_bytes()).unwrap();
+
+        let config: Config = confy::load_path(file.path()).unwrap();
+        let pipeline = config.pipeline("spec").unwrap();
+        assert_eq!(pipeline.steps.len(), 2);
+        assert_eq!(pipeline.steps[0].style, "refine");
+        assert_eq!(pipeline.steps[0].provider.as_deref(), Some("ollama"));
+        assert_eq!(pipeline.steps[1].provider, None);
+
+        let err = config.pipeline("empty").unwrap_err().to_string();
+        assert_eq!(err, "Pipeline 'empty' has no steps.");
+        let err = config.pipeline("nope").unwrap_err().to_string();
+        assert!(err.contains("Available pipelines: empty, spec"));
+
+        // Configs without pipelines still load.
+        assert!(Config::default().pipelines.is_empty());
+    }
+
+    #[test]
+    fn test_load_postprocess() {
+        let mut file = NamedTempFile::new().unwrap();
+        let toml = r#"
+[llm]
+provider = "claude"
+default_prompt = "code-gen"
+postprocess = [{ kind = "trim" }]
+
+[providers.claude]
+api_key = "CLAUDE_API_KEY"
+model = "claude-sonnet-4-20250514"
+
+[[prompts]]
+name = "code-gen"
+prompt = "Write the code"
+postprocess = [
+    { kind = "extract_code", language = "rust" },
+    { kind = "normalize_line_endings" },
+]
+"#;
+        file.write_all(toml.as_bytes()).unwrap();
+
+        let config: Config = confy::load_path(file.path()).unwrap();
+        assert_eq!(config.llm.postprocess, vec![Processor::Trim]);
+        assert_eq!(
+            config.prompts[0].postprocess,
+            Some(vec![
+                Processor::ExtractCode {
+                    language: Some("rust".to_string()),
+                    all: false,
+                },
+                Processor::NormalizeLineEndings,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_load_schema() {
+        let mut file = NamedTempFile::new().unwrap();
+        let toml = r#"
+[llm]
+provider = "claude"
+default_prompt = "ticket"
+
+[providers.claude]
+api_key = "CLAUDE_API_KEY"
+model = "claude-sonnet-4-20250514"
+
+[[prompts]]
+name = "ticket"
+prompt = "Turn this into a ticket"
+schema_retries = 3
+temperature = 0.2
+
+[prompts.schema]
+type = "object"
+required = ["title"]
+properties = { title = { type = "string" } }
+
+[[prompts]]
+name = "review"
+prompt = "Review this"
+schema = "schemas/review.json"
+
+[prompts.chunking]
+strategy = "paragraphs"
+reduce_prompt = "Merge these reviews"
+"#;
+        file.write_all(toml.as_bytes()).unwrap();
+
+        let config: Config = confy::load_path(file.path()).unwrap();
+        assert_eq!(
+            config.prompts[0].schema,
+            Some(SchemaSource::Inline(serde_json::json!({
+                "type": "object",
+                "required": ["title"],
+                "properties": {"title": {"type": "string"}},
+            })))
+        );
+        assert_eq!(config.prompts[0].schema_retries, Some(3));
+        assert_eq!(config.prompts[0].params.temperature, Some(0.2));
+        assert_eq!(
+            config.prompts[1].schema,
+            Some(SchemaSource::Path("schemas/review.json".into()))
+        );
+        assert_eq!(config.prompts[0].chunking, None);
+        assert_eq!(
+            config.prompts[1].chunking,
+            Some(Chunking {
+                strategy: crate::chunk::ChunkStrategy::Paragraphs,
+                max_tokens: crate::chunk::DEFAULT_MAX_TOKENS,
+                reduce_prompt: Some("Merge these reviews".to_string()),
+            })
+        );
+    }
 }
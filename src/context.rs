@@ -0,0 +1,263 @@
+use anyhow::Result;
+use globset::GlobBuilder;
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+
+/// Upper bound on the combined size of the attached files, unless
+/// `--max-context-bytes` says otherwise.
+pub const DEFAULT_MAX_BYTES: u64 = 256 * 1024;
+
+/// A file attached to the prompt as context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// The `@path` references of `text` that name existing files. Trailing
+/// punctuation is ignored, so "see @src/main.rs." refers to `src/main.rs`.
+pub fn references(text: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        let Some(reference) = word.strip_prefix('@') else {
+            continue;
+        };
+        let reference = [
+            reference,
+            reference.trim_end_matches(['.', ',', ';', ':', '!', '?', ')']),
+        ]
+        .into_iter()
+        .find(|candidate| !candidate.is_empty() && Path::new(candidate).is_file());
+        if let Some(reference) = reference
+            && !paths.iter().any(|p| p == reference)
+        {
+            paths.push(reference.to_string());
+        }
+    }
+    paths
+}
+
+/// Walks `dir`, honouring `.gitignore` files and skipping hidden entries.
+fn walk(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in WalkBuilder::new(dir).require_git(false).build() {
+        let entry = entry?;
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            let path = entry.path();
+            files.push(path.strip_prefix("./").unwrap_or(path).to_path_buf());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The files matching a glob, found by walking from its longest literal
+/// directory prefix.
+fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>> {
+    let matcher = GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Invalid pattern '{}': {}", pattern, e))?
+        .compile_matcher();
+    let base: PathBuf = Path::new(pattern)
+        .components()
+        .take_while(|component| !is_glob(&component.as_os_str().to_string_lossy()))
+        .collect();
+    let base = if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base
+    };
+    Ok(walk(&base)?
+        .into_iter()
+        .filter(|path| matcher.is_match(path))
+        .collect())
+}
+
+/// The files named by `patterns`: plain paths, directories (walked
+/// recursively) or globs, without duplicates. `flag` names the option the
+/// patterns come from in error messages.
+pub fn expand(patterns: &[String], flag: &str) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for pattern in patterns {
+        let path = Path::new(pattern);
+        let matches = if path.is_file() {
+            vec![path.to_path_buf()]
+        } else if path.is_dir() {
+            walk(path)?
+        } else if is_glob(pattern) {
+            expand_glob(pattern)?
+        } else {
+            return Err(anyhow::anyhow!("File '{}' not found", pattern));
+        };
+        if matches.is_empty() {
+            return Err(anyhow::anyhow!("{} '{}' matches no files", flag, pattern));
+        }
+        for path in matches {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Reads the files named by `patterns`, as [`expand`] finds them. Fails
+/// before reading anything when the files add up to more than `max_bytes`.
+pub fn collect(patterns: &[String], max_bytes: u64) -> Result<Vec<ContextFile>> {
+    let paths = expand(patterns, "--context")?;
+
+    let mut total: u64 = 0;
+    for path in &paths {
+        total += std::fs::metadata(path)?.len();
+    }
+    if total > max_bytes {
+        return Err(anyhow::anyhow!(
+            "Context files add up to {} bytes, over the {} byte limit. Attach fewer files or raise --max-context-bytes",
+            total,
+            max_bytes
+        ));
+    }
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let content = std::fs::read_to_string(&path).map_err(|e| {
+                anyhow::anyhow!("Failed to read context file '{}': {}", path.display(), e)
+            })?;
+            Ok(ContextFile { path, content })
+        })
+        .collect()
+}
+
+/// Formats the files as delimited blocks to append to the input.
+pub fn render(files: &[ContextFile]) -> String {
+    files
+        .iter()
+        .map(|file| {
+            format!(
+                "<file path=\"{}\">\n{}{}</file>",
+                file.path.display(),
+                file.content,
+                if file.content.ends_with('\n') {
+                    ""
+                } else {
+                    "\n"
+                }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out a small project and returns its root.
+    fn project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/llm")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(root.join("src/llm/mod.rs"), "pub mod claude;\n").unwrap();
+        std::fs::write(root.join("src/debug.log"), "noise").unwrap();
+        std::fs::write(root.join("target/out.rs"), "built").unwrap();
+        std::fs::write(root.join("README.md"), "# Demo").unwrap();
+        dir
+    }
+
+    fn collected(root: &Path, patterns: &[&str]) -> Vec<String> {
+        let patterns: Vec<String> = patterns
+            .iter()
+            .map(|p| root.join(p).to_string_lossy().into_owned())
+            .collect();
+        collect(&patterns, DEFAULT_MAX_BYTES)
+            .unwrap()
+            .into_iter()
+            .map(|f| {
+                f.path
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_collect_files_dirs_and_globs() {
+        let dir = project();
+        let root = dir.path();
+        assert_eq!(collected(root, &["README.md"]), vec!["README.md"]);
+        assert_eq!(
+            collected(root, &["src"]),
+            vec!["src/llm/mod.rs", "src/main.rs"]
+        );
+        assert_eq!(collected(root, &["src/*.rs"]), vec!["src/main.rs"]);
+        assert_eq!(
+            collected(root, &["**/*.rs", "src/main.rs"]),
+            vec!["src/llm/mod.rs", "src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let dir = project();
+        let pattern = |p: &str| vec![dir.path().join(p).to_string_lossy().into_owned()];
+
+        let err = collect(&pattern("missing.rs"), DEFAULT_MAX_BYTES)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("File '") && err.ends_with("missing.rs' not found"));
+
+        let err = collect(&pattern("src/*.py"), DEFAULT_MAX_BYTES)
+            .unwrap_err()
+            .to_string();
+        assert!(err.ends_with("matches no files"));
+
+        let err = collect(&pattern("src"), 10).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Context files add up to 29 bytes, over the 10 byte limit. Attach fewer files or raise --max-context-bytes"
+        );
+    }
+
+    #[test]
+    fn test_references() {
+        let dir = project();
+        let main = dir.path().join("src/main.rs");
+        let text = format!(
+            "Fix @{} (see @{}.) and email me@example.com or @nobody",
+            main.display(),
+            main.display()
+        );
+        assert_eq!(references(&text), vec![main.to_string_lossy().into_owned()]);
+        assert!(references("No references here.").is_empty());
+    }
+
+    #[test]
+    fn test_render() {
+        let files = vec![
+            ContextFile {
+                path: "src/main.rs".into(),
+                content: "fn main() {}\n".to_string(),
+            },
+            ContextFile {
+                path: "notes.txt".into(),
+                content: "no newline".to_string(),
+            },
+        ];
+        assert_eq!(
+            render(&files),
+            "<file path=\"src/main.rs\">\nfn main() {}\n</file>\n\n<file path=\"notes.txt\">\nno newline\n</file>"
+        );
+        assert_eq!(render(&[]), "");
+    }
+}
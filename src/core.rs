@@ -1,60 +1,1007 @@
-use super::cli::{Cli, Commands};
-use super::config::Config;
-use super::llm::r#trait::LlmBackend;
+use super::backup::{self, Store};
+use super::batch;
+use super::chat;
+use super::chunk::{self, Chunking, ChunkingOverrides};
+use super::cli::{Cli, Commands, OutputFormat};
+use super::config::{Config, Provider};
+use super::context;
+use super::diff;
+use super::extract;
+use super::llm::error::LlmError;
+use super::llm::r#trait::{
+    GenerationParams, LlmBackend, LlmRequest, LlmResponse, TextStream, Usage,
+};
+use super::postprocess::PostProcessor;
+use super::schema::{self, JsonSchema};
+use super::template;
 use anyhow::Result;
 use console::style;
+use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::time::Duration;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-pub async fn run(cli: Cli, config: Config, llm_backend: Box<dyn LlmBackend>) -> Result<()> {
+/// Collects the variables for the style templates: the input file name plus
+/// the `--var` values. Checked before any input is read so that a typo does
+/// not cost the user what they typed in the editor.
+pub(crate) fn template_vars(
+    style_texts: &[&str],
+    file: Option<&str>,
+    user_vars: Vec<(String, String)>,
+) -> Result<BTreeMap<String, String>> {
+    let mut used: Vec<String> = Vec::new();
+    for style_text in style_texts {
+        for name in template::placeholders(style_text) {
+            if !used.contains(&name) {
+                used.push(name);
+            }
+        }
+    }
+    let mut vars = BTreeMap::new();
+    if let Some(file) = file {
+        vars.insert("filename".to_string(), file.to_string());
+    }
+    for (key, value) in user_vars {
+        if !used.contains(&key) {
+            return Err(anyhow::anyhow!(
+                "Unknown template variable '{}': the style does not use it. Its variables are: {}",
+                key,
+                if used.is_empty() {
+                    "none".to_string()
+                } else {
+                    used.join(", ")
+                }
+            ));
+        }
+        vars.insert(key, value);
+    }
+    if let Some(missing) = used
+        .iter()
+        .find(|name| *name != template::INPUT && !vars.contains_key(*name))
+    {
+        return Err(anyhow::anyhow!(
+            "Template variable '{{{{{}}}}}' has no value. Pass it with --var {}=<value>, or write \\{{{{{}}}}} to keep it as text",
+            missing,
+            missing,
+            missing
+        ));
+    }
+    Ok(vars)
+}
+
+/// Where `optimize` reads its input from.
+#[derive(Debug, PartialEq, Eq)]
+enum InputSource {
+    Text(String),
+    File(String),
+    Stdin,
+    Editor,
+}
+
+/// Picks the input source from the command line. Without an explicit one,
+/// piped stdin is read rather than opening an editor nobody can type into.
+fn input_source(
+    input: Option<String>,
+    file: Option<String>,
+    stdin: bool,
+    editor: bool,
+    stdin_is_terminal: bool,
+) -> Result<InputSource> {
+    if input.is_some() && file.is_some() {
+        return Err(anyhow::anyhow!(
+            "Cannot specify both --input and --file options. Use one or the other."
+        ));
+    }
+    if stdin && (input.is_some() || file.is_some()) {
+        return Err(anyhow::anyhow!(
+            "Cannot combine --stdin with --input or --file. Use one input source."
+        ));
+    }
+    if stdin && editor {
+        return Err(anyhow::anyhow!(
+            "Cannot combine --stdin with --editor. Use one input source."
+        ));
+    }
+    Ok(match (input, file) {
+        (Some(input), _) => InputSource::Text(input),
+        (_, Some(file)) if file == "-" => InputSource::Stdin,
+        (_, Some(file)) => InputSource::File(file),
+        _ if stdin || (!editor && !stdin_is_terminal) => InputSource::Stdin,
+        _ => InputSource::Editor,
+    })
+}
+
+/// The buffer the editor opens with when no input is given: a blank line to
+/// type on, then commented guidance naming the styles and providers the
+/// input goes through and, if enabled, the previous input.
+fn editor_template(
+    config: &Config,
+    steps: &[Step],
+    pipeline: Option<&str>,
+    previous_input: Option<&str>,
+) -> String {
+    let marker = &config.editor.comment_marker;
+    if marker.is_empty() {
+        // Nothing would be stripped, so the guidance would be sent along.
+        return String::new();
+    }
+    let mut guidance = format!(
+        "Write the input above. Lines starting with '{}' are ignored,\nand an empty input aborts.\n",
+        marker
+    );
+    if let Some(name) = pipeline {
+        guidance += &match config
+            .pipelines
+            .get(name)
+            .and_then(|p| p.description.as_deref())
+        {
+            Some(description) => format!("\nPipeline: {} - {}\n", name, description),
+            None => format!("\nPipeline: {}\n", name),
+        };
+    }
+    for step in steps {
+        let description = config
+            .prompts
+            .iter()
+            .find(|prompt| prompt.name == step.label)
+            .and_then(|prompt| prompt.description.as_deref());
+        guidance += &match description {
+            Some(description) => format!("\nStyle:    {} - {}\n", step.label, description),
+            None => format!("\nStyle:    {}\n", step.label),
+        };
+        guidance += &format!(
+            "Provider: {} ({})\n",
+            step.provider_name, step.provider.model
+        );
+    }
+    if let Some(previous) = previous_input.filter(|previous| !previous.trim().is_empty()) {
+        guidance += &format!("\nPrevious input:\n\n{}", previous);
+    }
+    format!("\n{}", super::editor::comment(&guidance, marker))
+}
+
+/// Opens the editor on [`editor_template`] and returns what was typed. The
+/// input is kept for the next run when `editor.previous_input` is on.
+fn read_editor(
+    config: &Config,
+    steps: &[Step],
+    pipeline: Option<&str>,
+    editor_cmd: &str,
+) -> Result<String> {
+    let settings = &config.editor;
+    let history_file = settings
+        .history_file
+        .as_deref()
+        .filter(|_| settings.previous_input);
+    let previous_input = history_file.and_then(|path| std::fs::read_to_string(path).ok());
+
+    let temp_file = tempfile::Builder::new().suffix(".md").tempfile()?;
+    std::fs::write(
+        temp_file.path(),
+        editor_template(config, steps, pipeline, previous_input.as_deref()),
+    )?;
+    super::editor::open_editor(temp_file.path(), editor_cmd)?;
+    let content = super::editor::read_editor_input(temp_file.path(), &settings.comment_marker)?;
+
+    if let Some(path) = history_file
+        && !content.trim().is_empty()
+    {
+        let saved = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(path, &content));
+        if let Err(e) = saved {
+            log::debug!("Could not save the input to '{}': {}", path.display(), e);
+        }
+    }
+    Ok(content)
+}
+
+/// Renders the style with the user's input. Styles without an `{{input}}`
+/// placeholder get the input appended after a blank line.
+pub(crate) fn build_prompt(
+    style_text: &str,
+    input: &str,
+    mut vars: BTreeMap<String, String>,
+) -> Result<String> {
+    vars.insert(template::INPUT.to_string(), input.to_string());
+    let rendered = template::render(style_text, &vars)?;
+    if template::placeholders(style_text)
+        .iter()
+        .any(|name| name == template::INPUT)
+    {
+        Ok(rendered)
+    } else {
+        Ok(format!("{}\n\n{}", rendered, input))
+    }
+}
+
+/// Whether a model name returned by `list_models` designates `model`. Ollama
+/// lists `llama3.2:latest` for a model pulled as `llama3.2`.
+fn is_same_model(listed: &str, model: &str) -> bool {
+    listed == model || listed.strip_suffix(":latest") == Some(model)
+}
+
+/// Checks that `model` is offered by the backend, suggesting the closest
+/// names on a typo. Backends that cannot list their models are trusted.
+pub(crate) async fn validate_model(
+    llm_backend: &dyn LlmBackend,
+    provider_name: &str,
+    model: &str,
+) -> Result<()> {
+    let models = match llm_backend.list_models().await {
+        Ok(models) => models,
+        Err(e) => {
+            log::debug!("Could not list models, skipping validation: {}", e);
+            return Ok(());
+        }
+    };
+    if models.iter().any(|listed| is_same_model(listed, model)) {
+        return Ok(());
+    }
+
+    let mut candidates: Vec<(f64, &String)> = models
+        .iter()
+        .map(|listed| (strsim::jaro_winkler(listed, model), listed))
+        .filter(|(score, _)| *score > 0.8)
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let suggestions: Vec<&str> = candidates
+        .iter()
+        .take(3)
+        .map(|(_, listed)| listed.as_str())
+        .collect();
+
+    let mut message = format!(
+        "Model '{}' is not available from provider '{}'.",
+        model, provider_name
+    );
+    if !suggestions.is_empty() {
+        message.push_str(&format!(" Did you mean: {}?", suggestions.join(", ")));
+    } else {
+        message.push_str(&format!(
+            " Run `inkspect list-models --provider {}` to see the available models.",
+            provider_name
+        ));
+    }
+    Err(anyhow::anyhow!(message))
+}
+
+/// Prints the response to stdout as it arrives and returns the full text.
+/// The first line is held back until it is complete so that conversational
+/// openers can still be dropped before anything reaches the terminal.
+pub(crate) async fn print_stream(
+    mut deltas: TextStream,
+    spinner: &ProgressBar,
+    postprocessor: &PostProcessor,
+) -> Result<String> {
+    let mut stdout = io::stdout();
+    let mut response = String::new();
+    let mut first_line_checked = false;
+
+    while let Some(delta) = deltas.next().await {
+        let delta = delta?;
+        spinner.finish_and_clear();
+        response.push_str(&delta);
+
+        if first_line_checked {
+            write!(stdout, "{}", delta)?;
+        } else if let Some(end) = response.find('\n') {
+            first_line_checked = true;
+            if postprocessor.is_leading_chatter(&response[..end]) {
+                write!(stdout, "{}", &response[end + 1..])?;
+            } else {
+                write!(stdout, "{}", response)?;
+            }
+        }
+        stdout.flush()?;
+    }
+
+    if !first_line_checked && !postprocessor.is_leading_chatter(&response) {
+        write!(stdout, "{}", response)?;
+    }
+    writeln!(stdout)?;
+    Ok(response)
+}
+
+/// One LLM call of `optimize`: the style to apply and where to send it.
+pub(crate) struct Step {
+    /// Style name, or `prompt` for a `--prompt` given on the command line.
+    pub(crate) label: String,
+    pub(crate) style_text: String,
+    pub(crate) provider_name: String,
+    pub(crate) provider: Provider,
+    pub(crate) params: GenerationParams,
+    pub(crate) postprocessor: PostProcessor,
+    pub(crate) schema: Option<JsonSchema>,
+    pub(crate) schema_retries: u32,
+    pub(crate) chunking: Option<Chunking>,
+}
+
+impl Step {
+    /// The templates this step renders: the style and its reduce prompt.
+    pub(crate) fn templates(&self) -> Vec<&str> {
+        let reduce_prompt = self
+            .chunking
+            .as_ref()
+            .and_then(|c| c.reduce_prompt.as_deref());
+        std::iter::once(self.style_text.as_str())
+            .chain(reduce_prompt)
+            .collect()
+    }
+
+    /// The chunks of `input` when it exceeds the step's chunking budget.
+    pub(crate) fn chunks<'a>(&self, input: &'a str) -> Option<Vec<&'a str>> {
+        let chunking = self.chunking.as_ref()?;
+        let chunks = chunk::split(input, chunking.strategy, chunking.max_tokens);
+        (chunks.len() > 1).then_some(chunks)
+    }
+}
+
+/// Sends `request` until the response matches the step's JSON Schema, feeding
+/// the validation errors back to the model between attempts. Returns the
+/// last response with the validated document, pretty-printed, as its text
+/// and the usage of every attempt.
+pub(crate) async fn request_json(
+    llm_backend: &dyn LlmBackend,
+    request: &LlmRequest,
+    step: &Step,
+    schema: &JsonSchema,
+) -> Result<LlmResponse> {
+    let mut attempt_request = request.clone();
+    let mut attempts = 1;
+    let mut usage: Option<Usage> = None;
+    loop {
+        let response = llm_backend.request(&attempt_request).await?;
+        log::debug!(
+            "Stop reason: {:?}, usage: {:?}",
+            response.stop_reason,
+            response.usage
+        );
+        usage = total_usage([usage, response.usage]);
+        let errors = match schema.validate(&step.postprocessor.apply(&response.text)) {
+            Ok(document) => {
+                return Ok(LlmResponse {
+                    text: serde_json::to_string_pretty(&document)?,
+                    usage,
+                    ..response
+                });
+            }
+            Err(errors) => errors,
+        };
+        if attempts > step.schema_retries {
+            return Err(LlmError::SchemaMismatch(format!(
+                "The response of '{}' does not match its JSON Schema after {} attempt(s):\n- {}",
+                step.label,
+                attempts,
+                errors.join("\n- ")
+            ))
+            .into());
+        }
+        log::debug!(
+            "Attempt {} does not match the JSON Schema: {:?}",
+            attempts,
+            errors
+        );
+        attempts += 1;
+        attempt_request.prompt = schema::retry_prompt(&request.prompt, &response.text, &errors);
+    }
+}
+
+/// Resolves a style into a [`Step`]. An explicit provider overrides the
+/// style's binding, model included; `model` and `cli_params` override both.
+pub(crate) fn resolve_step(
+    config: &Config,
+    style_name: Option<&str>,
+    dynamic_prompt: Option<String>,
+    provider: Option<String>,
+    model: Option<&String>,
+    cli_params: &GenerationParams,
+    cli_chunking: &ChunkingOverrides,
+) -> Result<Step> {
+    let prompt_style = if dynamic_prompt.is_some() {
+        None
+    } else {
+        let style_key = style_name.unwrap_or(&config.llm.default_prompt);
+        let prompt_style = config
+            .prompts
+            .iter()
+            .find(|p| p.name == style_key)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Prompt style '{}' not found in configuration.", style_key)
+            })?;
+        Some(prompt_style)
+    };
+
+    let explicit_provider = provider.is_some();
+    let provider_name = provider
+        .or_else(|| prompt_style.and_then(|p| p.provider.clone()))
+        .unwrap_or_else(|| config.llm.provider.clone());
+    let mut provider_config = config.provider(&provider_name)?;
+    let mut params = provider_config.params.clone();
+    if let Some(prompt_style) = prompt_style {
+        if let Some(model) = &prompt_style.model
+            && !explicit_provider
+        {
+            provider_config.model = model.clone();
+        }
+        params = params.overridden_by(&prompt_style.params);
+    }
+    if let Some(model) = model {
+        provider_config.model = model.clone();
+    }
+    let schema = match prompt_style {
+        Some(prompt_style) => prompt_style
+            .schema
+            .as_ref()
+            .map(|source| {
+                JsonSchema::load(source)
+                    .map_err(|e| anyhow::anyhow!("Prompt style '{}': {}", prompt_style.name, e))
+            })
+            .transpose()?,
+        None => None,
+    };
+    let chunking = cli_chunking.apply(prompt_style.and_then(|p| p.chunking.as_ref()));
+    if schema.is_some()
+        && let Some(chunking) = &chunking
+        && chunking.reduce_prompt.is_none()
+    {
+        return Err(anyhow::anyhow!(
+            "Prompt style '{}' has a JSON Schema, so chunking it needs a reduce prompt to merge the chunks into one document",
+            prompt_style.map_or("prompt", |p| p.name.as_str())
+        ));
+    }
+
+    Ok(Step {
+        label: prompt_style.map_or_else(|| "prompt".to_string(), |p| p.name.clone()),
+        style_text: dynamic_prompt
+            .or_else(|| prompt_style.map(|p| p.prompt.clone()))
+            .unwrap_or_default(),
+        provider_name,
+        provider: provider_config,
+        params: params.overridden_by(cli_params),
+        postprocessor: PostProcessor::new(
+            prompt_style
+                .and_then(|p| p.postprocess.as_deref())
+                .unwrap_or(&config.llm.postprocess),
+        )?,
+        schema,
+        schema_retries: prompt_style
+            .and_then(|p| p.schema_retries)
+            .unwrap_or(schema::DEFAULT_RETRIES),
+        chunking,
+    })
+}
+
+/// Runs a step over an input split into chunks: the style on each chunk's
+/// prompt in `prompts`, then the reduce prompt, if any, over the partial
+/// results. Without a reduce prompt the results are joined in order. The
+/// text returned is post-processed already.
+pub(crate) async fn map_reduce(
+    llm_backend: &dyn LlmBackend,
+    request: &LlmRequest,
+    step: &Step,
+    prompts: Vec<String>,
+    vars: &BTreeMap<String, String>,
+    spinner: &ProgressBar,
+) -> Result<LlmResponse> {
+    let mut parts = Vec::new();
+    let mut usage: Option<Usage> = None;
+    for (index, prompt) in prompts.iter().enumerate() {
+        spinner.set_message(format!(
+            "Chunk {}/{}: running '{}', please wait...",
+            index + 1,
+            prompts.len(),
+            step.label
+        ));
+        let response = llm_backend
+            .request(&LlmRequest {
+                prompt: prompt.clone(),
+                json_schema: None,
+                ..request.clone()
+            })
+            .await?;
+        log::debug!(
+            "Chunk {} stop reason: {:?}, usage: {:?}",
+            index + 1,
+            response.stop_reason,
+            response.usage
+        );
+        usage = total_usage([usage, response.usage]);
+        parts.push(step.postprocessor.apply(&response.text));
+    }
+
+    let Some(reduce_prompt) = step
+        .chunking
+        .as_ref()
+        .and_then(|c| c.reduce_prompt.as_deref())
+    else {
+        return Ok(LlmResponse {
+            text: parts.join("\n\n"),
+            stop_reason: None,
+            usage,
+        });
+    };
+    spinner.set_message(format!(
+        "Merging {} chunks of '{}', please wait...",
+        parts.len(),
+        step.label
+    ));
+    let merged_input = parts
+        .iter()
+        .enumerate()
+        .map(|(index, part)| {
+            format!(
+                "<part index=\"{}\">\n{}\n</part>",
+                index + 1,
+                part.trim_end()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let reduce_request = LlmRequest {
+        prompt: build_prompt(reduce_prompt, &merged_input, vars.clone())?,
+        ..request.clone()
+    };
+    let response = match &step.schema {
+        Some(schema) => request_json(llm_backend, &reduce_request, step, schema).await?,
+        None => {
+            let response = llm_backend.request(&reduce_request).await?;
+            LlmResponse {
+                text: step.postprocessor.apply(&response.text),
+                ..response
+            }
+        }
+    };
+    Ok(LlmResponse {
+        usage: total_usage([usage, response.usage]),
+        ..response
+    })
+}
+
+/// Sums the token counts that were reported, if any were.
+fn total_usage(usages: impl IntoIterator<Item = Option<Usage>>) -> Option<Usage> {
+    usages.into_iter().flatten().reduce(|total, usage| Usage {
+        input_tokens: total.input_tokens + usage.input_tokens,
+        output_tokens: total.output_tokens + usage.output_tokens,
+    })
+}
+
+fn sha256_hex(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// What `--format json` reports about one LLM call.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct StepReport {
+    style: String,
+    provider: String,
+    model: String,
+    usage: Option<Usage>,
+    latency_ms: u64,
+    finish_reason: Option<String>,
+}
+
+/// The document `optimize --format json` prints. For a pipeline, the top
+/// level describes the last step with usage and latency summed over all of
+/// them, and `steps` details each one.
+#[derive(Serialize, Debug)]
+struct Envelope<'a> {
+    response: &'a str,
+    #[serde(flatten)]
+    summary: StepReport,
+    input_sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pipeline: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    steps: &'a [StepReport],
+    /// Files written by `--extract-to`, relative to its directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<&'a [extract::ExtractedFile]>,
+}
+
+impl<'a> Envelope<'a> {
+    fn new(
+        response: &'a str,
+        input_sha256: String,
+        reports: &'a [StepReport],
+        pipeline: Option<&'a str>,
+        files: Option<&'a [extract::ExtractedFile]>,
+    ) -> Self {
+        let last = reports.last().expect("optimize runs at least one step");
+        Self {
+            response,
+            summary: StepReport {
+                usage: total_usage(reports.iter().map(|r| r.usage)),
+                latency_ms: reports.iter().map(|r| r.latency_ms).sum(),
+                ..last.clone()
+            },
+            input_sha256,
+            pipeline,
+            steps: if pipeline.is_some() { reports } else { &[] },
+            files,
+        }
+    }
+}
+
+/// Prints a JSON document, compact on one line for NDJSON.
+fn print_json<T: Serialize + ?Sized>(format: OutputFormat, value: &T) -> Result<()> {
+    if format == OutputFormat::Ndjson {
+        println!("{}", serde_json::to_string(value)?);
+    } else {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    }
+    Ok(())
+}
+
+/// Prints `items` as one JSON array, or as one object per line for NDJSON.
+pub(crate) fn print_json_list<T: Serialize>(format: OutputFormat, items: &[T]) -> Result<()> {
+    if format == OutputFormat::Ndjson {
+        for item in items {
+            print_json(format, item)?;
+        }
+        Ok(())
+    } else {
+        print_json(format, items)
+    }
+}
+
+/// An entry of `list-models --format json`.
+#[derive(Serialize)]
+struct ModelEntry<'a> {
+    name: &'a str,
+    default: bool,
+}
+
+/// An entry of `list-prompts --format json`.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum PromptEntry<'a> {
+    Style {
+        name: &'a str,
+        description: Option<&'a str>,
+        provider: Option<&'a str>,
+        model: Option<&'a str>,
+    },
+    Pipeline {
+        name: &'a str,
+        description: Option<&'a str>,
+        steps: Vec<&'a str>,
+    },
+}
+
+/// An entry of `versions --format json`, also printed for what `undo`
+/// restored.
+#[derive(Serialize)]
+struct VersionEntry<'a> {
+    /// 1 for the newest version.
+    version: usize,
+    path: &'a Path,
+    timestamp: jiff::Timestamp,
+    style: &'a str,
+    provider: &'a str,
+    bytes: usize,
+}
+
+impl<'a> VersionEntry<'a> {
+    fn new(index: usize, version: &'a backup::Version) -> Self {
+        Self {
+            version: index,
+            path: &version.path,
+            timestamp: version.timestamp,
+            style: &version.style,
+            provider: &version.provider,
+            bytes: version.content.len(),
+        }
+    }
+}
+
+fn local_time(timestamp: jiff::Timestamp) -> String {
+    timestamp
+        .to_zoned(jiff::tz::TimeZone::system())
+        .strftime("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+pub(crate) fn spinner_style() -> Result<ProgressStyle> {
+    Ok(ProgressStyle::default_spinner()
+        .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"])
+        .template("{spinner:.rgb(181,126,220)} {msg:.rgb(181,126,220)}")?)
+}
+
+/// Starts a spinner on `spinner`, a bar that may belong to a [`MultiProgress`].
+pub(crate) fn start_spinner(
+    spinner: ProgressBar,
+    style: ProgressStyle,
+    message: String,
+) -> ProgressBar {
+    spinner.set_style(style);
+    spinner.set_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+pub(crate) fn spinner(message: String) -> Result<ProgressBar> {
+    Ok(start_spinner(
+        ProgressBar::new_spinner(),
+        spinner_style()?,
+        message,
+    ))
+}
+
+/// Replaces `path` with `content` atomically. What the file held is saved
+/// in the backup store first, along with the style and provider, given as
+/// `written_by`, that produced the new content.
+pub(crate) fn write_file(
+    path: &Path,
+    content: &str,
+    store: Option<&Store>,
+    written_by: &(String, String),
+) -> Result<()> {
+    if let Some(store) = store
+        && let Ok(original) = std::fs::read_to_string(path)
+        && original != content
+    {
+        store.record(path, original, &written_by.0, &written_by.1)?;
+    }
+    backup::write_atomic(path, content)
+}
+
+/// What `--diff` and `--confirm` ask for before a file is written.
+struct Preview<'a> {
+    diff: bool,
+    confirm: bool,
+    editor: &'a str,
+    format: OutputFormat,
+}
+
+/// Shows the changes writing `content` to `path` makes, if asked to, and
+/// with `--confirm` lets the user accept them, refuse them or edit the
+/// content first. Returns the content to write, or `None` when refused.
+fn preview_write(
+    path: &Path,
+    mut content: String,
+    preview: &Preview,
+    answers: &mut dyn BufRead,
+) -> Result<Option<String>> {
+    if !preview.diff && !preview.confirm {
+        return Ok(Some(content));
+    }
+    let original = match std::fs::read_to_string(path) {
+        Ok(original) => original,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(anyhow::anyhow!(
+                "Failed to read '{}': {}",
+                path.display(),
+                e
+            ));
+        }
+    };
+    let mut show = true;
+    loop {
+        if show {
+            let changes = diff::unified(&original, &content, &path.display().to_string());
+            let changes = if changes.is_empty() {
+                format!("No changes to {}", path.display())
+            } else {
+                diff::colorize(&changes)
+            };
+            // Keep stdout parseable in the JSON formats.
+            if preview.format == OutputFormat::Text {
+                println!("{}", changes);
+            } else {
+                eprintln!("{}", changes);
+            }
+        }
+        if !preview.confirm {
+            return Ok(Some(content));
+        }
+
+        eprint!("Write these changes to {}? [y/n/e] ", path.display());
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        answers.read_line(&mut answer)?;
+        show = false;
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(Some(content)),
+            "n" | "no" | "" => {
+                eprintln!("Left {} unchanged.", path.display());
+                return Ok(None);
+            }
+            "e" | "edit" => {
+                content = super::editor::edit_text(&content, preview.editor)
+                    .map_err(|e| anyhow::anyhow!("{}. Left {} unchanged", e, path.display()))?;
+                show = true;
+            }
+            _ => eprintln!("Please answer y (write), n (skip) or e (edit)."),
+        }
+    }
+}
+
+/// Builds the backend for a named provider entry. Injected into [`run`] so
+/// that the backend is only created once the style has been resolved.
+pub type BackendFactory = dyn Fn(&str, &Provider) -> Result<Box<dyn LlmBackend>>;
+
+pub async fn run(cli: Cli, config: Config, create_backend: &BackendFactory) -> Result<()> {
+    let format = cli.format;
     match cli.command {
         Commands::Optimize {
             input,
             file,
+            stdin,
             editor,
+            provider,
+            model,
             style,
             prompt: dynamic_prompt,
+            pipeline,
+            keep_intermediate,
+            vars: user_vars,
+            context: context_patterns,
+            max_context_bytes,
+            chunk,
+            chunk_tokens,
+            reduce_prompt,
             output,
             in_place,
+            review,
+            diff: show_diff,
+            confirm,
+            patch,
+            extract_to,
+            dry_run,
+            overwrite,
             no_system_prompt,
-            ..
+            temperature,
+            top_p,
+            max_output_tokens,
+            stop_sequences,
+            seed,
         } => {
-            let style_text = if let Some(p) = dynamic_prompt {
-                p
-            } else {
-                let style_key = style.as_deref().unwrap_or(&config.llm.default_prompt);
-                let prompt_style = config
-                    .prompts
+            let cli_params = GenerationParams {
+                temperature,
+                top_p,
+                max_output_tokens,
+                stop_sequences: (!stop_sequences.is_empty()).then_some(stop_sequences),
+                seed,
+            };
+            let cli_chunking = ChunkingOverrides {
+                strategy: chunk,
+                max_tokens: chunk_tokens.map(|tokens| tokens as usize),
+                reduce_prompt,
+            };
+            let steps = if let Some(pipeline_name) = &pipeline {
+                config
+                    .pipeline(pipeline_name)?
+                    .steps
                     .iter()
-                    .find(|p| p.name == style_key)
-                    .ok_or_else(|| {
-                        anyhow::anyhow!("Prompt style '{}' not found in configuration.", style_key)
-                    })?;
-                prompt_style.prompt.clone()
+                    .map(|step| {
+                        resolve_step(
+                            &config,
+                            Some(&step.style),
+                            None,
+                            provider.clone().or_else(|| step.provider.clone()),
+                            model.as_ref(),
+                            &cli_params,
+                            &cli_chunking,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            } else {
+                vec![resolve_step(
+                    &config,
+                    style.as_deref(),
+                    dynamic_prompt,
+                    provider,
+                    model.as_ref(),
+                    &cli_params,
+                    &cli_chunking,
+                )?]
             };
 
-            // Validate that --input and --file are not used together
-            if input.is_some() && file.is_some() {
+            // A model belongs to one provider, so --model cannot follow a
+            // pipeline across several of them.
+            if let (Some(model), Some(pipeline_name)) = (&model, &pipeline) {
+                let mut providers: Vec<&str> = steps
+                    .iter()
+                    .map(|step| step.provider_name.as_str())
+                    .collect();
+                providers.sort();
+                providers.dedup();
+                if providers.len() > 1 {
+                    return Err(anyhow::anyhow!(
+                        "Pipeline '{}' runs on several providers ({}), so --model '{}' is ambiguous. Pin one provider with --provider, or drop --model",
+                        pipeline_name,
+                        providers.join(", "),
+                        model
+                    ));
+                }
+            }
+            // Checked before the input is read or any step is sent, so that a
+            // mistyped model neither loses what was typed in the editor nor
+            // bills the first steps of a pipeline.
+            let backends = steps
+                .iter()
+                .map(|step| create_backend(&step.provider_name, &step.provider))
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(model) = &model {
+                validate_model(backends[0].as_ref(), &steps[0].provider_name, model).await?;
+            }
+
+            let source = input_source(
+                input,
+                file,
+                stdin,
+                editor.is_some(),
+                io::stdin().is_terminal(),
+            )?;
+            if in_place && source == InputSource::Stdin {
+                return Err(anyhow::anyhow!(
+                    "Cannot use --in-place with input read from stdin"
+                ));
+            }
+            if (show_diff || confirm) && output.is_none() && !in_place {
                 return Err(anyhow::anyhow!(
-                    "Cannot specify both --input and --file options. Use one or the other."
+                    "--diff and --confirm show the changes to a file. Use them with --output or --in-place"
                 ));
             }
+            if confirm && (source == InputSource::Stdin || !io::stdin().is_terminal()) {
+                return Err(anyhow::anyhow!(
+                    "--confirm needs an interactive terminal to answer on. Use --diff or --patch instead"
+                ));
+            }
+            // Checked before anything is sent: the patch must name the file
+            // as `git apply` will look for it.
+            let patch_name = match (&patch, &source) {
+                (Some(_), InputSource::File(file_path)) if Path::new(file_path).exists() => {
+                    Some(diff::patch_path(Path::new(file_path)).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Cannot write a patch for '{}': it is outside the current directory, where `git apply` looks for it. Run inkspect from a directory containing the file",
+                            file_path
+                        )
+                    })?)
+                }
+                _ => None,
+            };
 
-            let (prompt, input_file_path) = if let Some(input) = input {
-                (input, None)
-            } else if let Some(file_path) = file {
-                let content = std::fs::read_to_string(&file_path).map_err(|e| {
-                    anyhow::anyhow!("Failed to read input file '{}': {}", file_path, e)
-                })?;
-                (content, Some(file_path))
-            } else {
-                let temp_file = tempfile::NamedTempFile::new()?;
-                let editor_cmd = editor
-                    .or_else(|| std::env::var("EDITOR").ok())
-                    .unwrap_or_else(|| "vim".to_string());
-                super::editor::open_editor(temp_file.path(), &editor_cmd)?;
-                let content = super::editor::read_editor_input(temp_file.path())?;
-                (content, None)
+            let style_texts: Vec<&str> = steps.iter().flat_map(Step::templates).collect();
+            let input_file = match &source {
+                InputSource::File(file_path) => Some(file_path.as_str()),
+                _ => None,
+            };
+            let vars = template_vars(&style_texts, input_file, user_vars)?;
+
+            let editor_cmd = super::editor::command(editor);
+            // Piped input, such as someone else's diff, could name any file,
+            // so it may not pull in files by @path.
+            let piped = matches!(source, InputSource::Stdin);
+            let (prompt, input_file_path) = match source {
+                InputSource::Text(input) => (input, None),
+                InputSource::File(file_path) => {
+                    let content = std::fs::read_to_string(&file_path).map_err(|e| {
+                        anyhow::anyhow!("Failed to read input file '{}': {}", file_path, e)
+                    })?;
+                    (content, Some(file_path))
+                }
+                InputSource::Stdin => {
+                    let mut content = String::new();
+                    io::stdin()
+                        .read_to_string(&mut content)
+                        .map_err(|e| anyhow::anyhow!("Failed to read stdin: {}", e))?;
+                    (content, None)
+                }
+                InputSource::Editor => (
+                    read_editor(&config, &steps, pipeline.as_deref(), &editor_cmd)?,
+                    None,
+                ),
             };
 
             if prompt.trim().is_empty() {
@@ -62,104 +1009,370 @@ pub async fn run(cli: Cli, config: Config, llm_backend: Box<dyn LlmBackend>) ->
                 return Ok(());
             }
 
-            let full_prompt = if !no_system_prompt && config.llm.system_prompt.is_some() {
-                format!(
-                    "{}
-
-{}
-
-{}",
-                    config.llm.system_prompt.as_ref().unwrap(),
-                    style_text,
-                    prompt
-                )
+            // Files named by --context or @path in the input go along with
+            // the input of every step.
+            let mut context_patterns = context_patterns;
+            let references = context::references(&prompt);
+            if piped && !references.is_empty() {
+                eprintln!(
+                    "{} Ignoring @path references in piped input: {}. Attach them with --context",
+                    console::style("Warning:").yellow(),
+                    references.join(", ")
+                );
             } else {
-                format!(
-                    "{}
-
-{}",
-                    style_text, prompt
-                )
+                for reference in references {
+                    if !context_patterns.contains(&reference) {
+                        context_patterns.push(reference);
+                    }
+                }
+            }
+            let context_files = context::collect(&context_patterns, max_context_bytes)?;
+            if !context_files.is_empty() {
+                let paths: Vec<String> = context_files
+                    .iter()
+                    .map(|file| file.path.display().to_string())
+                    .collect();
+                eprintln!("Attaching context: {}", paths.join(", "));
+            }
+            let context_block = context::render(&context_files);
+            let with_context = |input: &str| {
+                if context_block.is_empty() {
+                    input.to_string()
+                } else {
+                    format!("{}\n\n{}", input, context_block)
+                }
             };
 
-            log::debug!(
-                "Using full prompt:
----
-{}
----",
-                full_prompt
-            );
+            if let Some(dir) = &keep_intermediate {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| anyhow::anyhow!("Failed to create directory '{}': {}", dir, e))?;
+            }
 
-            let spinner = ProgressBar::new_spinner();
-            spinner.set_style(
-                ProgressStyle::default_spinner()
-                    .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"])
-                    .template("{spinner:.rgb(181,126,220)} {msg:.rgb(181,126,220)}")?,
-            );
-            spinner.set_message("Optimizing prompt, please wait...");
-            spinner.enable_steady_tick(Duration::from_millis(100));
+            let input_sha256 = sha256_hex(&prompt);
+            let mut output_response = prompt;
+            let mut raw_response = String::new();
+            let mut streamed = false;
+            let mut reports = Vec::new();
+            for (index, step) in steps.iter().enumerate() {
+                let is_last = index + 1 == steps.len();
+                let request = LlmRequest {
+                    system_prompt: if no_system_prompt {
+                        None
+                    } else {
+                        config.llm.system_prompt.clone()
+                    },
+                    prompt: build_prompt(
+                        &step.style_text,
+                        &with_context(&output_response),
+                        vars.clone(),
+                    )?,
+                    params: step.params.clone(),
+                    json_schema: step.schema.as_ref().map(|s| s.value().clone()),
+                    ..Default::default()
+                };
+
+                log::debug!(
+                    "Using system prompt:\n---\n{}\n---",
+                    request.system_prompt.as_deref().unwrap_or("<none>")
+                );
+                log::debug!("Using prompt:\n---\n{}\n---", request.prompt);
+                log::debug!("Using generation params: {:?}", request.params);
+                log::debug!(
+                    "Using provider '{}' with model '{}'",
+                    step.provider_name,
+                    step.provider.model
+                );
+                let llm_backend = &backends[index];
 
-            let response = llm_backend.request(&full_prompt).await;
+                let spinner = spinner(if steps.len() > 1 {
+                    format!(
+                        "Step {}/{}: running '{}', please wait...",
+                        index + 1,
+                        steps.len(),
+                        step.label
+                    )
+                } else {
+                    "Optimizing prompt, please wait...".to_string()
+                })?;
 
-            spinner.finish_and_clear();
+                // Inputs over the chunking budget go through the style one
+                // chunk at a time, each with the context files.
+                let chunks = step.chunks(&output_response);
+                let chunked = chunks.is_some();
 
-            let response = response?;
+                // Stream straight to the terminal when stdout is the destination
+                // and the post-processing allows it; file outputs, patches, JSON
+                // output, chunked inputs and intermediate steps are collected in
+                // one go.
+                streamed = is_last
+                    && !chunked
+                    && format == OutputFormat::Text
+                    && output.is_none()
+                    && !in_place
+                    && !review
+                    && patch.is_none()
+                    && extract_to.is_none()
+                    && step.schema.is_none()
+                    && step.postprocessor.is_streamable();
+                let started = Instant::now();
+                let response = if let Some(chunks) = chunks {
+                    let prompts = chunks
+                        .iter()
+                        .map(|chunk| {
+                            build_prompt(&step.style_text, &with_context(chunk), vars.clone())
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    log::debug!("Split the input into {} chunks", prompts.len());
+                    map_reduce(
+                        llm_backend.as_ref(),
+                        &request,
+                        step,
+                        prompts,
+                        &vars,
+                        &spinner,
+                    )
+                    .await
+                } else if let Some(schema) = &step.schema {
+                    request_json(llm_backend.as_ref(), &request, step, schema).await
+                } else if streamed {
+                    match llm_backend.stream(&request).await {
+                        Ok(deltas) => print_stream(deltas, &spinner, &step.postprocessor)
+                            .await
+                            .map(|text| LlmResponse {
+                                text,
+                                ..Default::default()
+                            }),
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    llm_backend.request(&request).await.inspect(|response| {
+                        log::debug!(
+                            "Stop reason: {:?}, usage: {:?}",
+                            response.stop_reason,
+                            response.usage
+                        );
+                    })
+                };
 
-            let lines: Vec<&str> = response.lines().collect();
-            let first_line = lines.get(0).unwrap_or(&"");
+                spinner.finish_and_clear();
 
-            let prefixes_to_remove = [
-                "Of course.",
-                "Certainly.",
-                "Here is a refined and comprehensive explanation",
-                "Here's a refined and comprehensive explanation",
-                "Here is a refined version",
-                "Here's a refined version",
-            ];
+                let response = response?;
+                reports.push(StepReport {
+                    style: step.label.clone(),
+                    provider: step.provider_name.clone(),
+                    model: step.provider.model.clone(),
+                    usage: response.usage,
+                    latency_ms: started.elapsed().as_millis() as u64,
+                    finish_reason: response.stop_reason,
+                });
+                raw_response = response.text;
+                // Validated JSON and merged chunks have been post-processed already.
+                output_response = if step.schema.is_some() || chunked {
+                    raw_response.clone()
+                } else {
+                    step.postprocessor.apply(&raw_response)
+                };
 
-            let mut output_response = response.clone();
-            if prefixes_to_remove.iter().any(|p| first_line.contains(p)) {
-                output_response = lines
-                    .iter()
-                    .skip(1)
-                    .map(|s| *s)
-                    .collect::<Vec<&str>>()
-                    .join("\n");
+                if let Some(dir) = &keep_intermediate {
+                    let path = std::path::Path::new(dir).join(format!(
+                        "{:02}-{}.md",
+                        index + 1,
+                        step.label
+                    ));
+                    std::fs::write(&path, &output_response)?;
+                    log::debug!("Saved step {} output to {}", index + 1, path.display());
+                }
+                if !is_last && output_response.trim().is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Pipeline step {} ('{}') returned an empty response.",
+                        index + 1,
+                        step.label
+                    ));
+                }
+            }
+
+            // A human checkpoint before anything is written: the edited
+            // buffer replaces the response.
+            if review {
+                let reviewed = super::editor::edit_text(&output_response, &editor_cmd)
+                    .map_err(|e| anyhow::anyhow!("{}. Aborting without writing anything", e))?;
+                if reviewed.trim().is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "The reviewed response is empty. Aborting without writing anything"
+                    ));
+                }
+                output_response = reviewed;
             }
 
-            // Handle output: priority is --output, then --in-place with input file, then stdout
-            if let Some(output_path_str) = output {
+            // Handle output: priority is --patch, then --output, then --in-place
+            // with input file, then stdout
+            let store = Store::open(&config.backups);
+            let last_step = steps.last().expect("a pipeline has at least one step");
+            let written_by = (
+                pipeline.clone().unwrap_or_else(|| last_step.label.clone()),
+                last_step.provider_name.clone(),
+            );
+            let preview = Preview {
+                diff: show_diff,
+                confirm,
+                editor: &editor_cmd,
+                format,
+            };
+            if let Some(patch_path) = &patch {
+                let Some(file_path) = &input_file_path else {
+                    return Err(anyhow::anyhow!(
+                        "Cannot use --patch without specifying a --file input"
+                    ));
+                };
+                let original = std::fs::read_to_string(file_path)?;
+                std::fs::write(
+                    patch_path,
+                    diff::unified(
+                        &original,
+                        &output_response,
+                        patch_name.as_deref().unwrap_or(file_path),
+                    ),
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", patch_path, e))?;
+                if format == OutputFormat::Text {
+                    println!("Wrote patch: {}", patch_path);
+                }
+            } else if let Some(output_path_str) = output {
                 let output_path = std::path::Path::new(&output_path_str);
                 let absolute_path = if output_path.is_absolute() {
                     output_path.to_path_buf()
                 } else {
                     std::env::current_dir()?.join(output_path)
                 };
+                let Some(content) = preview_write(
+                    &absolute_path,
+                    output_response,
+                    &preview,
+                    &mut io::stdin().lock(),
+                )?
+                else {
+                    return Ok(());
+                };
+                output_response = content;
                 log::debug!("Saving output to: {}", absolute_path.display());
-                std::fs::write(&absolute_path, output_response)?;
+                write_file(
+                    &absolute_path,
+                    &output_response,
+                    store.as_ref(),
+                    &written_by,
+                )?;
                 log::debug!("Successfully wrote to {}", absolute_path.display());
             } else if in_place {
                 if let Some(file_path) = input_file_path {
+                    let Some(content) = preview_write(
+                        Path::new(&file_path),
+                        output_response,
+                        &preview,
+                        &mut io::stdin().lock(),
+                    )?
+                    else {
+                        return Ok(());
+                    };
+                    output_response = content;
                     log::debug!("Updating input file in-place: {}", file_path);
-                    std::fs::write(&file_path, output_response)?;
+                    write_file(
+                        Path::new(&file_path),
+                        &output_response,
+                        store.as_ref(),
+                        &written_by,
+                    )?;
                     log::debug!("Successfully updated {}", file_path);
-                    println!("Updated file: {}", file_path);
+                    if format == OutputFormat::Text {
+                        println!("Updated file: {}", file_path);
+                    }
                 } else {
                     return Err(anyhow::anyhow!(
                         "Cannot use --in-place without specifying a --file input"
                     ));
                 }
-            } else {
+            } else if !streamed && extract_to.is_none() && format == OutputFormat::Text {
                 println!("{}", output_response);
             }
+
+            // Code blocks are taken from the raw response, since
+            // post-processing may already have removed their fences.
+            let files = match &extract_to {
+                Some(dir) => Some(extract::extract(
+                    &raw_response,
+                    std::path::Path::new(dir),
+                    overwrite.unwrap_or_default(),
+                    dry_run,
+                )?),
+                None => None,
+            };
+
+            if format == OutputFormat::Text {
+                if let (Some(dir), Some(files)) = (&extract_to, &files) {
+                    for file in files {
+                        println!(
+                            "{} ({} lines, {})",
+                            std::path::Path::new(dir).join(&file.path).display(),
+                            file.lines,
+                            file.action.describe(dry_run)
+                        );
+                    }
+                }
+            } else {
+                let envelope = Envelope::new(
+                    &output_response,
+                    input_sha256,
+                    &reports,
+                    pipeline.as_deref(),
+                    files.as_deref(),
+                );
+                print_json(format, &envelope)?;
+            }
         }
-        Commands::ListModels { .. } => {
+        Commands::ListModels { provider } => {
+            let provider_name = provider.unwrap_or_else(|| config.llm.provider.clone());
+            let provider_config = config.provider(&provider_name)?;
+            let llm_backend = create_backend(&provider_name, &provider_config)?;
             let models = llm_backend.list_models().await?;
+            if format != OutputFormat::Text {
+                let entries: Vec<ModelEntry> = models
+                    .iter()
+                    .map(|model| ModelEntry {
+                        name: model,
+                        default: is_same_model(model, &provider_config.model),
+                    })
+                    .collect();
+                return print_json_list(format, &entries);
+            }
             for model in models {
-                println!("{}", model);
+                if is_same_model(&model, &provider_config.model) {
+                    println!("{} {}", model, style("(default)").green());
+                } else {
+                    println!("{}", model);
+                }
             }
         }
         Commands::ListPrompts => {
+            if format != OutputFormat::Text {
+                let mut prompts: Vec<_> = config.prompts.iter().collect();
+                prompts.sort_by_key(|p| &p.name);
+                let mut entries: Vec<PromptEntry> = prompts
+                    .into_iter()
+                    .map(|p| PromptEntry::Style {
+                        name: &p.name,
+                        description: p.description.as_deref(),
+                        provider: p.provider.as_deref(),
+                        model: p.model.as_deref(),
+                    })
+                    .collect();
+                entries.extend(config.pipelines.iter().map(|(name, pipeline)| {
+                    PromptEntry::Pipeline {
+                        name,
+                        description: pipeline.description.as_deref(),
+                        steps: pipeline.steps.iter().map(|s| s.style.as_str()).collect(),
+                    }
+                }));
+                return print_json_list(format, &entries);
+            }
             println!("{}", style("Available Prompts").bold().underlined());
 
             let mut prompts = config.prompts.clone();
@@ -171,6 +1384,88 @@ pub async fn run(cli: Cli, config: Config, llm_backend: Box<dyn LlmBackend>) ->
                     println!("  {}", description);
                 }
             }
+
+            if !config.pipelines.is_empty() {
+                println!("\n{}", style("Available Pipelines").bold().underlined());
+                for (name, pipeline) in &config.pipelines {
+                    println!("\n{}", style(name).bold().cyan());
+                    if let Some(description) = &pipeline.description {
+                        println!("  {}", description);
+                    }
+                    let styles: Vec<&str> =
+                        pipeline.steps.iter().map(|s| s.style.as_str()).collect();
+                    println!("  {}", styles.join(" -> "));
+                }
+            }
+        }
+        Commands::Batch(args) => batch::run(args, &config, format, create_backend).await?,
+        Commands::Undo { file, to } => {
+            let store = Store::open(&config.backups)
+                .ok_or_else(|| anyhow::anyhow!("Backups are disabled in the configuration"))?;
+            let path = match &file {
+                Some(file) => Some(PathBuf::from(file)),
+                None => store.latest()?.map(|entry| entry.version.path),
+            }
+            .ok_or_else(|| anyhow::anyhow!("No saved version of any file"))?;
+            let entry = store
+                .versions(&path)?
+                .into_iter()
+                .nth(to as usize - 1)
+                .ok_or_else(|| match to {
+                    1 => anyhow::anyhow!("No saved version of '{}'", path.display()),
+                    _ => anyhow::anyhow!("No version {} of '{}'", to, path.display()),
+                })?;
+            let version = &entry.version;
+            // What the file holds now is saved first, so an undo can itself
+            // be undone.
+            write_file(
+                &version.path,
+                &version.content,
+                Some(&store),
+                &("undo".to_string(), "inkspect".to_string()),
+            )?;
+            store.remove(&entry)?;
+            if format == OutputFormat::Text {
+                println!(
+                    "Restored {} to its version of {}, before '{}' on {}",
+                    version.path.display(),
+                    local_time(version.timestamp),
+                    version.style,
+                    version.provider
+                );
+            } else {
+                print_json(format, &VersionEntry::new(1, version))?;
+            }
+        }
+        Commands::Versions { file } => {
+            let store = Store::open(&config.backups)
+                .ok_or_else(|| anyhow::anyhow!("Backups are disabled in the configuration"))?;
+            let entries = store.versions(Path::new(&file))?;
+            if format != OutputFormat::Text {
+                let versions: Vec<VersionEntry> = entries
+                    .iter()
+                    .enumerate()
+                    .map(|(index, entry)| VersionEntry::new(index + 1, &entry.version))
+                    .collect();
+                return print_json_list(format, &versions);
+            }
+            if entries.is_empty() {
+                println!("No saved version of '{}'", file);
+            }
+            for (index, entry) in entries.iter().enumerate() {
+                let version = &entry.version;
+                println!(
+                    "{} {}  {} on {}, {} lines",
+                    style(format!("{:>3}", index + 1)).bold().cyan(),
+                    local_time(version.timestamp),
+                    version.style,
+                    version.provider,
+                    version.content.lines().count()
+                );
+            }
+        }
+        Commands::Chat(args) => {
+            chat::run(args, &config, create_backend, &mut io::stdin().lock()).await?
         }
         Commands::Setup { .. } => unreachable!(),
     }
@@ -178,18 +1473,23 @@ pub async fn run(cli: Cli, config: Config, llm_backend: Box<dyn LlmBackend>) ->
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::cli::Cli;
-    use crate::config::Config;
+    use crate::config::{Config, Prompt};
+    use crate::llm::r#trait::LlmResponse;
+    use crate::schema::SchemaSource;
     use clap::Parser;
 
     struct MockLlmBackend;
 
     #[async_trait::async_trait]
     impl LlmBackend for MockLlmBackend {
-        async fn request(&self, _full_prompt: &str) -> Result<String> {
-            Ok("Mocked response".to_string())
+        async fn request(&self, _request: &LlmRequest) -> Result<LlmResponse> {
+            Ok(LlmResponse {
+                text: "Mocked response".to_string(),
+                ..Default::default()
+            })
         }
 
         async fn list_models(&self) -> Result<Vec<String>> {
@@ -197,12 +1497,15 @@ mod tests {
         }
     }
 
+    fn mock_backend(_name: &str, _provider: &Provider) -> Result<Box<dyn LlmBackend>> {
+        Ok(Box::new(MockLlmBackend))
+    }
+
     #[tokio::test]
     async fn test_run_optimize_with_input() {
         let cli = Cli::parse_from(vec!["inkspect", "optimize", "--input", "test prompt"]);
         let config = Config::default();
-        let llm_backend = Box::new(MockLlmBackend);
-        let result = run(cli, config, llm_backend).await;
+        let result = run(cli, config, &mock_backend).await;
         assert!(result.is_ok());
     }
 
@@ -210,8 +1513,7 @@ mod tests {
     async fn test_run_list_models() {
         let cli = Cli::parse_from(vec!["inkspect", "list-models"]);
         let config = Config::default();
-        let llm_backend = Box::new(MockLlmBackend);
-        let result = run(cli, config, llm_backend).await;
+        let result = run(cli, config, &mock_backend).await;
         assert!(result.is_ok());
     }
 
@@ -219,8 +1521,7 @@ mod tests {
     async fn test_run_list_prompts() {
         let cli = Cli::parse_from(vec!["inkspect", "list-prompts"]);
         let config = Config::default();
-        let llm_backend = Box::new(MockLlmBackend);
-        let result = run(cli, config, llm_backend).await;
+        let result = run(cli, config, &mock_backend).await;
         assert!(result.is_ok());
     }
 
@@ -228,8 +1529,7 @@ mod tests {
     async fn test_run_optimize_with_empty_input() {
         let cli = Cli::parse_from(vec!["inkspect", "optimize", "--input", ""]);
         let config = Config::default();
-        let llm_backend = Box::new(MockLlmBackend);
-        let result = run(cli, config, llm_backend).await;
+        let result = run(cli, config, &mock_backend).await;
         assert!(result.is_ok());
     }
 
@@ -249,8 +1549,7 @@ mod tests {
             file_path.to_str().unwrap(),
         ]);
         let config = Config::default();
-        let llm_backend = Box::new(MockLlmBackend);
-        let result = run(cli, config, llm_backend).await;
+        let result = run(cli, config, &mock_backend).await;
         assert!(result.is_ok());
     }
 
@@ -271,8 +1570,7 @@ mod tests {
             "--in-place",
         ]);
         let config = Config::default();
-        let llm_backend = Box::new(MockLlmBackend);
-        let result = run(cli, config, llm_backend).await;
+        let result = run(cli, config, &mock_backend).await;
         assert!(result.is_ok());
 
         // Verify the file was updated
@@ -280,6 +1578,20 @@ mod tests {
         assert_eq!(updated_content, "Mocked response");
     }
 
+    #[tokio::test]
+    async fn test_print_stream_returns_full_response() {
+        let deltas: TextStream = Box::pin(futures_util::stream::iter(vec![
+            Ok("Certainly.\nFirst".to_string()),
+            Ok(" line\nSecond".to_string()),
+        ]));
+        let spinner = ProgressBar::hidden();
+        let postprocessor = PostProcessor::new(&crate::postprocess::default_processors()).unwrap();
+        let response = print_stream(deltas, &spinner, &postprocessor)
+            .await
+            .unwrap();
+        assert_eq!(response, "Certainly.\nFirst line\nSecond");
+    }
+
     #[tokio::test]
     async fn test_run_optimize_conflicting_input_options() {
         let cli = Cli::parse_from(vec![
@@ -291,8 +1603,7 @@ mod tests {
             "test.txt",
         ]);
         let config = Config::default();
-        let llm_backend = Box::new(MockLlmBackend);
-        let result = run(cli, config, llm_backend).await;
+        let result = run(cli, config, &mock_backend).await;
         assert!(result.is_err());
         assert!(
             result
@@ -312,8 +1623,7 @@ mod tests {
             "--in-place",
         ]);
         let config = Config::default();
-        let llm_backend = Box::new(MockLlmBackend);
-        let result = run(cli, config, llm_backend).await;
+        let result = run(cli, config, &mock_backend).await;
         assert!(result.is_err());
         assert!(
             result
@@ -322,4 +1632,1407 @@ mod tests {
                 .contains("Cannot use --in-place without specifying a --file input")
         );
     }
+
+    type Respond = dyn Fn(&LlmRequest) -> Result<String> + Send + Sync;
+
+    /// Answers through `respond`, "Mocked response" by default, and records
+    /// the requests it receives and whether any was streamed. Clones share the
+    /// script and the records, so a test keeps one to inspect them.
+    #[derive(Clone)]
+    pub(crate) struct ScriptedLlmBackend {
+        respond: std::sync::Arc<Respond>,
+        requests: std::sync::Arc<std::sync::Mutex<Vec<LlmRequest>>>,
+        streamed: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl Default for ScriptedLlmBackend {
+        fn default() -> Self {
+            Self::respond(|_| Ok("Mocked response".to_string()))
+        }
+    }
+
+    impl ScriptedLlmBackend {
+        pub(crate) fn respond(
+            respond: impl Fn(&LlmRequest) -> Result<String> + Send + Sync + 'static,
+        ) -> Self {
+            Self {
+                respond: std::sync::Arc::new(respond),
+                requests: Default::default(),
+                streamed: Default::default(),
+            }
+        }
+
+        /// Answers with `text` every time.
+        pub(crate) fn fixed(text: &'static str) -> Self {
+            Self::respond(move |_| Ok(text.to_string()))
+        }
+
+        /// Answers with `responses` in turn.
+        pub(crate) fn script(responses: Vec<&'static str>) -> Self {
+            let responses = std::sync::Mutex::new(responses);
+            Self::respond(move |_| Ok(responses.lock().unwrap().remove(0).to_string()))
+        }
+
+        /// A backend factory handing out this backend for every provider.
+        pub(crate) fn factory(
+            &self,
+        ) -> impl Fn(&str, &Provider) -> Result<Box<dyn LlmBackend>> + use<> {
+            let backend = self.clone();
+            move |_, _| Ok(Box::new(backend.clone()))
+        }
+
+        pub(crate) fn requests(&self) -> std::sync::MutexGuard<'_, Vec<LlmRequest>> {
+            self.requests.lock().unwrap()
+        }
+
+        pub(crate) fn streamed(&self) -> bool {
+            self.streamed.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl LlmBackend for ScriptedLlmBackend {
+        async fn request(&self, request: &LlmRequest) -> Result<LlmResponse> {
+            self.requests.lock().unwrap().push(request.clone());
+            Ok(LlmResponse {
+                text: (self.respond)(request)?,
+                ..Default::default()
+            })
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>> {
+            Ok(vec![])
+        }
+
+        async fn stream(&self, request: &LlmRequest) -> Result<TextStream> {
+            self.streamed
+                .store(true, std::sync::atomic::Ordering::SeqCst);
+            let text = self.request(request).await?.text;
+            Ok(Box::pin(futures_util::stream::once(
+                async move { Ok(text) },
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_merges_generation_params() {
+        let mut config = Config::default();
+        let claude = config.providers.get_mut("claude").unwrap();
+        claude.params.temperature = Some(0.9);
+        claude.params.max_output_tokens = Some(1000);
+        claude.params.top_p = Some(0.5);
+        let style = config
+            .prompts
+            .iter_mut()
+            .find(|p| p.name == "code-spec")
+            .unwrap();
+        style.params.temperature = Some(0.4);
+        style.params.max_output_tokens = Some(8000);
+
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "test prompt",
+            "--provider",
+            "claude",
+            "--max-output-tokens",
+            "16000",
+            "--stop",
+            "END",
+        ]);
+        let backend = ScriptedLlmBackend::default();
+        let create_backend = backend.factory();
+        run(cli, config, &create_backend).await.unwrap();
+
+        let requests = backend.requests();
+        assert_eq!(
+            requests[0].params,
+            GenerationParams {
+                temperature: Some(0.4),
+                top_p: Some(0.5),
+                max_output_tokens: Some(16000),
+                stop_sequences: Some(vec!["END".to_string()]),
+                seed: None,
+            }
+        );
+    }
+
+    /// Runs `optimize` with `args` and returns the provider name and model the
+    /// backend was built for.
+    async fn resolved_backend(config: Config, args: &[&str]) -> (String, String) {
+        let mut argv = vec!["inkspect", "optimize", "--input", "test prompt"];
+        argv.extend_from_slice(args);
+        let cli = Cli::parse_from(argv);
+        let built = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let backend_built = built.clone();
+        let create_backend =
+            move |name: &str, provider: &Provider| -> Result<Box<dyn LlmBackend>> {
+                *backend_built.lock().unwrap() = Some((name.to_string(), provider.model.clone()));
+                Ok(Box::new(MockLlmBackend))
+            };
+        run(cli, config, &create_backend).await.unwrap();
+        built.lock().unwrap().take().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_uses_style_provider_and_model() {
+        let mut config = Config::default();
+        let style = config
+            .prompts
+            .iter_mut()
+            .find(|p| p.name == "code-gen")
+            .unwrap();
+        style.provider = Some("gemini".to_string());
+        style.model = Some("models/gemini-2.5-pro".to_string());
+
+        assert_eq!(
+            resolved_backend(config.clone(), &["--style", "code-gen"]).await,
+            ("gemini".to_string(), "models/gemini-2.5-pro".to_string())
+        );
+        // An explicit provider wins, and the style's model does not follow it.
+        assert_eq!(
+            resolved_backend(
+                config.clone(),
+                &["--style", "code-gen", "--provider", "claude"]
+            )
+            .await,
+            ("claude".to_string(), "claude-sonnet-4-20250514".to_string())
+        );
+        // Styles without a binding use the configured default provider.
+        assert_eq!(
+            resolved_backend(config, &["--style", "code-spec"]).await,
+            ("claude".to_string(), "claude-sonnet-4-20250514".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_style_model_without_provider() {
+        let mut config = Config::default();
+        config.prompts[0].model = Some("claude-opus-4-20250514".to_string());
+        let style = config.prompts[0].name.clone();
+
+        assert_eq!(
+            resolved_backend(config.clone(), &["--style", &style]).await,
+            ("claude".to_string(), "claude-opus-4-20250514".to_string())
+        );
+        assert_eq!(
+            resolved_backend(config, &["--style", &style, "--provider", "ollama"]).await,
+            ("ollama".to_string(), "llama3.2".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_model_flag_overrides_style() {
+        let mut config = Config::default();
+        config.prompts[0].model = Some("claude-opus-4-20250514".to_string());
+        let style = config.prompts[0].name.clone();
+
+        assert_eq!(
+            resolved_backend(config, &["--style", &style, "--model", "model2"]).await,
+            ("claude".to_string(), "model2".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_rejects_unknown_model() {
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "test prompt",
+            "--model",
+            "modle1",
+        ]);
+        let err = run(cli, Config::default(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Model 'modle1' is not available from provider 'claude'"));
+        assert!(err.contains("Did you mean: model1"));
+
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "test prompt",
+            "--model",
+            "gpt-4o",
+        ]);
+        let err = run(cli, Config::default(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("inkspect list-models --provider claude"));
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_pipeline_checks_model_first() {
+        let mut config = Config::default();
+        config.pipelines.insert(
+            "spec".to_string(),
+            crate::config::Pipeline {
+                description: None,
+                steps: vec![
+                    crate::config::PipelineStep {
+                        style: "code-debug".to_string(),
+                        provider: Some("ollama".to_string()),
+                    },
+                    crate::config::PipelineStep {
+                        style: "code-spec".to_string(),
+                        provider: None,
+                    },
+                ],
+            },
+        );
+        let backend = ScriptedLlmBackend::default();
+        let optimize = |extra: &[&str]| {
+            let mut args = vec![
+                "inkspect",
+                "optimize",
+                "--input",
+                "rough idea",
+                "--pipeline",
+                "spec",
+            ];
+            args.extend_from_slice(extra);
+            Cli::parse_from(args)
+        };
+
+        let err = run(
+            optimize(&["--model", "llama3.2"]),
+            config.clone(),
+            &backend.factory(),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with(
+            "Pipeline 'spec' runs on several providers (claude, ollama), so --model 'llama3.2' is ambiguous"
+        ));
+
+        // Pinned to one provider, the model is checked before any step runs.
+        let err = run(
+            optimize(&["--provider", "ollama", "--model", "llama3.2"]),
+            config,
+            &backend.factory(),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("Model 'llama3.2' is not available from provider 'ollama'"));
+        assert!(backend.requests().is_empty());
+    }
+
+    #[test]
+    fn test_is_same_model() {
+        assert!(is_same_model("llama3.2:latest", "llama3.2"));
+        assert!(is_same_model("llama3.2:latest", "llama3.2:latest"));
+        assert!(!is_same_model("llama3.2:1b", "llama3.2"));
+    }
+
+    #[test]
+    fn test_input_source() {
+        let text = || Some("idea".to_string());
+        let file = |path: &str| Some(path.to_string());
+
+        assert_eq!(
+            input_source(text(), None, false, false, false).unwrap(),
+            InputSource::Text("idea".to_string())
+        );
+        assert_eq!(
+            input_source(None, file("a.md"), false, false, false).unwrap(),
+            InputSource::File("a.md".to_string())
+        );
+        assert_eq!(
+            input_source(None, file("-"), false, false, true).unwrap(),
+            InputSource::Stdin
+        );
+        assert_eq!(
+            input_source(None, None, true, false, true).unwrap(),
+            InputSource::Stdin
+        );
+        // Piped stdin is picked up unless an editor was asked for.
+        assert_eq!(
+            input_source(None, None, false, false, false).unwrap(),
+            InputSource::Stdin
+        );
+        assert_eq!(
+            input_source(None, None, false, true, false).unwrap(),
+            InputSource::Editor
+        );
+        assert_eq!(
+            input_source(None, None, false, false, true).unwrap(),
+            InputSource::Editor
+        );
+    }
+
+    #[test]
+    fn test_input_source_conflicts() {
+        let err = |input: Option<&str>, file: Option<&str>, stdin: bool, editor: bool| {
+            input_source(
+                input.map(String::from),
+                file.map(String::from),
+                stdin,
+                editor,
+                true,
+            )
+            .unwrap_err()
+            .to_string()
+        };
+        assert!(err(Some("x"), Some("a.md"), false, false).contains("both --input and --file"));
+        assert!(err(Some("x"), None, true, false).contains("Cannot combine --stdin"));
+        assert!(err(None, Some("-"), true, false).contains("Cannot combine --stdin"));
+        assert!(err(None, None, true, true).contains("--stdin with --editor"));
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_in_place_with_stdin() {
+        let cli = Cli::parse_from(vec!["inkspect", "optimize", "--file", "-", "--in-place"]);
+        let err = run(cli, Config::default(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Cannot use --in-place with input read from stdin");
+    }
+
+    #[test]
+    fn test_build_prompt_without_placeholders_appends_input() {
+        let vars = template_vars(&["Refine this."], None, vec![]).unwrap();
+        assert_eq!(
+            build_prompt("Refine this.", "my idea", vars).unwrap(),
+            "Refine this.\n\nmy idea"
+        );
+    }
+
+    #[test]
+    fn test_build_prompt_fills_template() {
+        let style_text = "Port {{filename}} to {{language}}:\n{{input}}";
+        let vars = template_vars(
+            &[style_text],
+            Some("main.py"),
+            vec![("language".to_string(), "Rust".to_string())],
+        )
+        .unwrap();
+        assert_eq!(
+            build_prompt(style_text, "print(1)", vars).unwrap(),
+            "Port main.py to Rust:\nprint(1)"
+        );
+    }
+
+    #[test]
+    fn test_template_vars_errors() {
+        let err = template_vars(
+            &["Refine this."],
+            None,
+            vec![("language".to_string(), "Rust".to_string())],
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("Unknown template variable 'language'"));
+        assert!(err.contains("Its variables are: none"));
+
+        let err = template_vars(&["Port {{filename}}: {{input}}"], None, vec![])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'{{filename}}' has no value"));
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_unfilled_variable() {
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "test prompt",
+            "--prompt",
+            "Translate to {{language}}: {{input}}",
+        ]);
+        let err = run(cli, Config::default(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("--var language=<value>"));
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_pipeline_chains_steps() {
+        let mut config = Config::default();
+        config.pipelines.insert(
+            "spec".to_string(),
+            crate::config::Pipeline {
+                description: None,
+                steps: vec![
+                    crate::config::PipelineStep {
+                        style: "code-debug".to_string(),
+                        provider: Some("ollama".to_string()),
+                    },
+                    crate::config::PipelineStep {
+                        style: "code-spec".to_string(),
+                        provider: None,
+                    },
+                ],
+            },
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let steps_dir = dir.path().join("steps");
+        let output_path = dir.path().join("out.md");
+
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "rough idea",
+            "--pipeline",
+            "spec",
+            "--keep-intermediate",
+            steps_dir.to_str().unwrap(),
+            "--output",
+            output_path.to_str().unwrap(),
+        ]);
+        let backend = ScriptedLlmBackend::default();
+        let providers = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (shared_backend, backend_providers) = (backend.clone(), providers.clone());
+        let create_backend = move |name: &str, _: &Provider| -> Result<Box<dyn LlmBackend>> {
+            backend_providers.lock().unwrap().push(name.to_string());
+            Ok(Box::new(shared_backend.clone()))
+        };
+        run(cli, config, &create_backend).await.unwrap();
+
+        assert_eq!(*providers.lock().unwrap(), vec!["ollama", "claude"]);
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].prompt.ends_with("rough idea"));
+        assert!(requests[1].prompt.ends_with("Mocked response"));
+        assert_eq!(
+            std::fs::read_to_string(steps_dir.join("01-code-debug.md")).unwrap(),
+            "Mocked response"
+        );
+        assert!(steps_dir.join("02-code-spec.md").exists());
+        assert_eq!(
+            std::fs::read_to_string(output_path).unwrap(),
+            "Mocked response"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_unknown_pipeline() {
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "test prompt",
+            "--pipeline",
+            "nope",
+        ]);
+        let err = run(cli, Config::default(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Pipeline 'nope' not found"));
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_applies_style_postprocess() {
+        use crate::postprocess::Processor;
+
+        let mut config = Config::default();
+        config.llm.postprocess = vec![Processor::Trim];
+        let style = config
+            .prompts
+            .iter_mut()
+            .find(|p| p.name == "code-gen")
+            .unwrap();
+        style.postprocess = Some(vec![Processor::ExtractCode {
+            language: Some("rust".to_string()),
+            all: false,
+        }]);
+        let dir = tempfile::tempdir().unwrap();
+        let code_path = dir.path().join("main.rs");
+        let spec_path = dir.path().join("spec.md");
+        let create_backend =
+            ScriptedLlmBackend::fixed("Certainly.\nHere you go:\n```rust\nfn main() {}\n```\n\n")
+                .factory();
+
+        for (style, path) in [("code-gen", &code_path), ("code-spec", &spec_path)] {
+            let cli = Cli::parse_from(vec![
+                "inkspect",
+                "optimize",
+                "--input",
+                "hello world",
+                "--style",
+                style,
+                "--output",
+                path.to_str().unwrap(),
+            ]);
+            run(cli, config.clone(), &create_backend).await.unwrap();
+        }
+        assert_eq!(
+            std::fs::read_to_string(&code_path).unwrap(),
+            "fn main() {}\n"
+        );
+        // Styles without their own processors use the global ones.
+        assert_eq!(
+            std::fs::read_to_string(&spec_path).unwrap(),
+            "Certainly.\nHere you go:\n```rust\nfn main() {}\n```"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_invalid_postprocess_regex() {
+        let mut config = Config::default();
+        config.llm.postprocess = vec![crate::postprocess::Processor::StripChatter {
+            leading: vec!["[".to_string()],
+            trailing: vec![],
+        }];
+        let cli = Cli::parse_from(vec!["inkspect", "optimize", "--input", "test prompt"]);
+        let err = run(cli, config, &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Invalid post-processing regex '['"));
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_extract_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let create_backend =
+            ScriptedLlmBackend::fixed("Certainly.\n```rust src/main.rs\nfn main() {}\n```\n")
+                .factory();
+        let cli = |extra: &[&str]| {
+            let mut args = vec![
+                "inkspect".to_string(),
+                "optimize".to_string(),
+                "--input".to_string(),
+                "a hello world".to_string(),
+                "--style".to_string(),
+                "code-gen".to_string(),
+                "--extract-to".to_string(),
+                dir.path().to_string_lossy().into_owned(),
+            ];
+            args.extend(extra.iter().map(|a| a.to_string()));
+            Cli::parse_from(args)
+        };
+
+        run(cli(&["--dry-run"]), Config::default(), &create_backend)
+            .await
+            .unwrap();
+        assert!(!dir.path().join("src/main.rs").exists());
+
+        run(cli(&[]), Config::default(), &create_backend)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+
+        let err = run(cli(&[]), Config::default(), &create_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Refusing to overwrite existing files"));
+        run(
+            cli(&["--overwrite", "always"]),
+            Config::default(),
+            &create_backend,
+        )
+        .await
+        .unwrap();
+    }
+
+    fn ticket_config() -> Config {
+        let mut config = Config::default();
+        config.prompts.push(Prompt {
+            name: "ticket".to_string(),
+            prompt: "Turn this into a ticket:".to_string(),
+            schema: Some(SchemaSource::Inline(serde_json::json!({
+                "type": "object",
+                "properties": {"title": {"type": "string"}},
+                "required": ["title"],
+            }))),
+            schema_retries: Some(1),
+            ..Default::default()
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_json_schema_retries_until_valid() {
+        let backend = ScriptedLlmBackend::script(vec![
+            "Sure! The title is Fix login.",
+            "```json\n{\"title\": \"Fix login\"}\n```",
+        ]);
+        let create_backend = backend.factory();
+        let output = tempfile::NamedTempFile::new().unwrap();
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "login is broken",
+            "--style",
+            "ticket",
+            "--output",
+            output.path().to_str().unwrap(),
+        ]);
+
+        run(cli, ticket_config(), &create_backend).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(output.path()).unwrap(),
+            "{\n  \"title\": \"Fix login\"\n}"
+        );
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].json_schema.is_some());
+        assert!(requests[1].prompt.starts_with(&requests[0].prompt));
+        assert!(requests[1].prompt.contains("Sure! The title is Fix login."));
+        assert!(requests[1].prompt.contains("not valid JSON"));
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_json_schema_gives_up() {
+        let backend = ScriptedLlmBackend::script(vec!["{\"name\": 1}", "{\"title\": 2}"]);
+        let create_backend = backend.factory();
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "login is broken",
+            "--style",
+            "ticket",
+        ]);
+
+        let err = run(cli, ticket_config(), &create_backend)
+            .await
+            .unwrap_err();
+        assert_eq!(crate::llm::error::exit_code(&err), 19);
+        assert!(err.to_string().starts_with(
+            "The response of 'ticket' does not match its JSON Schema after 2 attempt(s):\n- at /title:"
+        ));
+        assert_eq!(backend.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_envelope() {
+        let report = |style: &str, usage: Option<Usage>, latency_ms: u64| StepReport {
+            style: style.to_string(),
+            provider: "claude".to_string(),
+            model: "claude-sonnet-4-20250514".to_string(),
+            usage,
+            latency_ms,
+            finish_reason: Some("end_turn".to_string()),
+        };
+        let usage = |input_tokens, output_tokens| {
+            Some(Usage {
+                input_tokens,
+                output_tokens,
+            })
+        };
+
+        let reports = [report("code-spec", usage(10, 20), 1500)];
+        let envelope = Envelope::new("Spec", sha256_hex("idea"), &reports, None, None);
+        assert_eq!(
+            serde_json::to_value(&envelope).unwrap(),
+            serde_json::json!({
+                "response": "Spec",
+                "style": "code-spec",
+                "provider": "claude",
+                "model": "claude-sonnet-4-20250514",
+                "usage": {"input_tokens": 10, "output_tokens": 20},
+                "latency_ms": 1500,
+                "finish_reason": "end_turn",
+                "input_sha256": sha256_hex("idea"),
+            })
+        );
+
+        let reports = [
+            report("refine", usage(10, 20), 1000),
+            report("code-spec", None, 500),
+            report("code-gen", usage(30, 40), 2000),
+        ];
+        let value = serde_json::to_value(Envelope::new(
+            "Code",
+            sha256_hex("idea"),
+            &reports,
+            Some("spec-to-code"),
+            None,
+        ))
+        .unwrap();
+        assert_eq!(value["style"], "code-gen");
+        assert_eq!(
+            value["usage"],
+            serde_json::json!({"input_tokens": 40, "output_tokens": 60})
+        );
+        assert_eq!(value["latency_ms"], 3500);
+        assert_eq!(value["pipeline"], "spec-to-code");
+        assert_eq!(value["steps"].as_array().unwrap().len(), 3);
+        assert_eq!(value["steps"][1]["usage"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_json_format() {
+        let backend = ScriptedLlmBackend::script(vec!["Optimized"]);
+        let create_backend = backend.factory();
+        let output = tempfile::NamedTempFile::new().unwrap();
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "--format",
+            "ndjson",
+            "optimize",
+            "--input",
+            "test prompt",
+            "--output",
+            output.path().to_str().unwrap(),
+        ]);
+
+        run(cli, Config::default(), &create_backend).await.unwrap();
+        assert_eq!(std::fs::read_to_string(output.path()).unwrap(), "Optimized");
+        assert_eq!(backend.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_attaches_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("lib.rs");
+        let notes = dir.path().join("notes.md");
+        std::fs::write(&lib, "pub fn add() {}\n").unwrap();
+        std::fs::write(&notes, "Keep it small.\n").unwrap();
+
+        let input = format!("Document this module, see @{}.", notes.display());
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            &input,
+            "--prompt",
+            "Refine this:",
+            "--context",
+            lib.to_str().unwrap(),
+        ]);
+        let backend = ScriptedLlmBackend::default();
+        let create_backend = backend.factory();
+        run(cli, Config::default(), &create_backend).await.unwrap();
+
+        assert_eq!(
+            backend.requests()[0].prompt,
+            format!(
+                "Refine this:\n\n{}\n\n<file path=\"{}\">\npub fn add() {{}}\n</file>\n\n<file path=\"{}\">\nKeep it small.\n</file>",
+                input,
+                lib.display(),
+                notes.display()
+            )
+        );
+
+        // References in an input file are followed too.
+        let input_file = dir.path().join("input.md");
+        std::fs::write(&input_file, &input).unwrap();
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--file",
+            input_file.to_str().unwrap(),
+            "--prompt",
+            "Refine this:",
+        ]);
+        run(cli, Config::default(), &create_backend).await.unwrap();
+        assert_eq!(
+            backend.requests()[1].prompt,
+            format!(
+                "Refine this:\n\n{}\n\n<file path=\"{}\">\nKeep it small.\n</file>",
+                input,
+                notes.display()
+            )
+        );
+    }
+
+    const DESIGN_DOC: &str = "# Design\n\nWhy we build it.\n\n## Storage\n\nData lives in SQLite.\n\n## API\n\nREST endpoints.\n";
+
+    #[tokio::test]
+    async fn test_run_optimize_maps_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = dir.path().join("design.md");
+        let out = dir.path().join("out.md");
+        std::fs::write(&doc, DESIGN_DOC).unwrap();
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--file",
+            doc.to_str().unwrap(),
+            "--prompt",
+            "Summarize:",
+            "--chunk",
+            "headings",
+            "--chunk-tokens",
+            "10",
+            "--output",
+            out.to_str().unwrap(),
+        ]);
+        let backend = ScriptedLlmBackend::default();
+        let create_backend = backend.factory();
+        run(cli, Config::default(), &create_backend).await.unwrap();
+
+        let prompts: Vec<String> = backend
+            .requests()
+            .iter()
+            .map(|r| r.prompt.clone())
+            .collect();
+        assert_eq!(
+            prompts,
+            vec![
+                "Summarize:\n\n# Design\n\nWhy we build it.\n\n",
+                "Summarize:\n\n## Storage\n\nData lives in SQLite.\n\n",
+                "Summarize:\n\n## API\n\nREST endpoints.\n",
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "Mocked response\n\nMocked response\n\nMocked response"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_reduces_chunks() {
+        let mut config = Config::default();
+        config.prompts.push(Prompt {
+            name: "summary".to_string(),
+            prompt: "Summarize:".to_string(),
+            postprocess: Some(vec![crate::postprocess::Processor::UnwrapFence]),
+            chunking: Some(Chunking {
+                strategy: chunk::ChunkStrategy::Paragraphs,
+                max_tokens: 6,
+                reduce_prompt: Some("Merge these summaries:\n{{input}}".to_string()),
+            }),
+            ..Default::default()
+        });
+        let backend =
+            ScriptedLlmBackend::script(vec!["```\nIntro.\n```", "Storage.", "API.", "Merged."]);
+        let create_backend = backend.factory();
+        let output = tempfile::NamedTempFile::new().unwrap();
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "Intro paragraph.\n\nStorage paragraph.\n\nAPI paragraph.",
+            "--style",
+            "summary",
+            "--output",
+            output.path().to_str().unwrap(),
+        ]);
+        run(cli, config, &create_backend).await.unwrap();
+
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[1].prompt, "Summarize:\n\nStorage paragraph.\n\n");
+        // Each partial result is post-processed before the merge.
+        assert_eq!(
+            requests[3].prompt,
+            "Merge these summaries:\n<part index=\"1\">\nIntro.\n</part>\n\n<part index=\"2\">\nStorage.\n</part>\n\n<part index=\"3\">\nAPI.\n</part>"
+        );
+        assert_eq!(std::fs::read_to_string(output.path()).unwrap(), "Merged.");
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_chunked_schema_needs_reduce_prompt() {
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--input",
+            "login is broken",
+            "--style",
+            "ticket",
+            "--chunk",
+            "paragraphs",
+        ]);
+        let err = run(cli, ticket_config(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with(
+            "Prompt style 'ticket' has a JSON Schema, so chunking it needs a reduce prompt"
+        ));
+    }
+
+    /// Writes a fake editor that replaces the buffer with `content`.
+    fn fake_editor(dir: &Path, content: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("editor.sh");
+        std::fs::write(
+            &path,
+            format!("#!/bin/sh\nprintf '%s' '{}' > \"$1\"\n", content),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_read_editor_prefills_guidance() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.editor.previous_input = true;
+        config.editor.history_file = Some(dir.path().join("data").join("last-input.md"));
+        let steps = vec![
+            resolve_step(
+                &config,
+                Some("code-spec"),
+                None,
+                Some("ollama".to_string()),
+                Some(&"llama3.2".to_string()),
+                &GenerationParams::default(),
+                &ChunkingOverrides::default(),
+            )
+            .unwrap(),
+        ];
+        let description = config
+            .prompts
+            .iter()
+            .find(|prompt| prompt.name == "code-spec")
+            .and_then(|prompt| prompt.description.clone())
+            .unwrap();
+
+        // Keeps the buffer it was opened on, then types the input above it.
+        let editor = dir.path().join("editor.sh");
+        std::fs::write(
+            &editor,
+            format!(
+                "#!/bin/sh\ncase \"$1\" in *.md) ;; *) exit 1 ;; esac\ncp \"$1\" '{0}/seen'\n{{ printf '# Todo app\\n\\nWith tags.\\n'; cat '{0}/seen'; }} > \"$1\"\n",
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let editor = editor.to_string_lossy();
+
+        let input = read_editor(&config, &steps, None, &editor).unwrap();
+        assert_eq!(input, "# Todo app\n\nWith tags.\n");
+        let seen = std::fs::read_to_string(dir.path().join("seen")).unwrap();
+        assert_eq!(
+            seen,
+            format!(
+                "\n; Write the input above. Lines starting with ';' are ignored,\n; and an empty input aborts.\n;\n; Style:    code-spec - {}\n; Provider: ollama (llama3.2)\n",
+                description
+            )
+        );
+
+        // The next run shows the input it was given, commented out.
+        read_editor(&config, &steps, None, &editor).unwrap();
+        let seen = std::fs::read_to_string(dir.path().join("seen")).unwrap();
+        assert!(seen.ends_with("; Previous input:\n;\n; # Todo app\n;\n; With tags.\n"));
+
+        config.editor.comment_marker = String::new();
+        read_editor(&config, &steps, None, &editor).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("seen")).unwrap(),
+            ""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_review_writes_edited_response() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("prompt.md");
+        std::fs::write(&file, "draft").unwrap();
+        let editor = fake_editor(dir.path(), "Reviewed by hand");
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--file",
+            file.to_str().unwrap(),
+            "--in-place",
+            "--review",
+            "--editor",
+            &editor,
+        ]);
+
+        run(cli, Config::default(), &mock_backend).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "Reviewed by hand");
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_review_empty_buffer_aborts() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("prompt.md");
+        std::fs::write(&file, "draft").unwrap();
+        let editor = fake_editor(dir.path(), "");
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--file",
+            file.to_str().unwrap(),
+            "--in-place",
+            "--review",
+            "--editor",
+            &editor,
+        ]);
+
+        let err = run(cli, Config::default(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "The reviewed response is empty. Aborting without writing anything"
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "draft");
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_review_failed_editor_aborts() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("prompt.md");
+        std::fs::write(&file, "draft").unwrap();
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "optimize",
+            "--file",
+            file.to_str().unwrap(),
+            "--in-place",
+            "--review",
+            "--editor",
+            "false",
+        ]);
+
+        let err = run(cli, Config::default(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "The editor 'false' exited with exit status: 1. Aborting without writing anything"
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "draft");
+    }
+
+    #[test]
+    fn test_preview_write_answers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prompt.md");
+        std::fs::write(&path, "draft\n").unwrap();
+        let editor = fake_editor(dir.path(), "edited by hand");
+        let preview = |diff, confirm| Preview {
+            diff,
+            confirm,
+            editor: &editor,
+            format: OutputFormat::Text,
+        };
+        let answer = |preview: &Preview, answers: &str| {
+            preview_write(
+                &path,
+                "optimized\n".to_string(),
+                preview,
+                &mut std::io::Cursor::new(answers.to_string()),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            answer(&preview(true, false), ""),
+            Some("optimized\n".to_string())
+        );
+        assert_eq!(answer(&preview(false, true), "maybe\nn\n"), None);
+        assert_eq!(answer(&preview(false, true), ""), None);
+        assert_eq!(
+            answer(&preview(false, true), "Y\n"),
+            Some("optimized\n".to_string())
+        );
+        assert_eq!(
+            answer(&preview(false, true), "e\nyes\n"),
+            Some("edited by hand".to_string())
+        );
+
+        let failing = Preview {
+            editor: "false",
+            ..preview(false, true)
+        };
+        let err = preview_write(
+            &path,
+            "optimized\n".to_string(),
+            &failing,
+            &mut std::io::Cursor::new("e\ny\n"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "The editor 'false' exited with exit status: 1. Left {} unchanged",
+                path.display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_patch_leaves_file_untouched() {
+        // Patches name files relative to the current directory.
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let name = dir.path().file_name().unwrap().to_str().unwrap();
+        let file = dir.path().join("prompt.md");
+        let patch = dir.path().join("prompt.patch");
+        std::fs::write(&file, "draft\n").unwrap();
+        let optimize = |file: &str| {
+            Cli::parse_from(vec![
+                "inkspect",
+                "optimize",
+                "--file",
+                file,
+                "--patch",
+                patch.to_str().unwrap(),
+            ])
+        };
+        let expected = format!(
+            "--- a/{0}/prompt.md\n+++ b/{0}/prompt.md\n@@ -1 +1 @@\n-draft\n+Mocked response\n\\ No newline at end of file\n",
+            name
+        );
+
+        let backend = ScriptedLlmBackend::default();
+        let absolute = std::fs::canonicalize(&file).unwrap();
+        for file_arg in [
+            format!("./{}/prompt.md", name),
+            absolute.display().to_string(),
+        ] {
+            run(optimize(&file_arg), Config::default(), &backend.factory())
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read_to_string(&patch).unwrap(), expected);
+        }
+        // The response goes to the patch only, not to the terminal.
+        assert!(!backend.streamed());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "draft\n");
+
+        let outside = tempfile::tempdir().unwrap();
+        let outside_file = outside.path().join("prompt.md");
+        std::fs::write(&outside_file, "draft\n").unwrap();
+        let err = run(
+            optimize(outside_file.to_str().unwrap()),
+            Config::default(),
+            &backend.factory(),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("it is outside the current directory"));
+        assert_eq!(backend.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_optimize_diff_needs_a_file_destination() {
+        let cli = Cli::parse_from(vec!["inkspect", "optimize", "--input", "idea", "--diff"]);
+        let err = run(cli, Config::default(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("--diff and --confirm show the changes to a file"));
+    }
+
+    #[tokio::test]
+    async fn test_run_undo_restores_in_place_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("prompt.md");
+        std::fs::write(&file, "draft").unwrap();
+        let mut config = Config::default();
+        config.backups.dir = Some(dir.path().join("backups"));
+        let optimize = || {
+            Cli::parse_from(vec![
+                "inkspect",
+                "optimize",
+                "--file",
+                file.to_str().unwrap(),
+                "--in-place",
+                "--style",
+                "code-gen",
+            ])
+        };
+
+        run(optimize(), config.clone(), &uppercase_backend().factory())
+            .await
+            .unwrap();
+        let optimized = std::fs::read_to_string(&file).unwrap();
+        assert_ne!(optimized, "draft");
+        // Writing the same content again saves no new version.
+        run(optimize(), config.clone(), &mock_backend)
+            .await
+            .unwrap();
+        run(optimize(), config.clone(), &mock_backend)
+            .await
+            .unwrap();
+
+        let store = Store::open(&config.backups).unwrap();
+        let versions = store.versions(&file).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].version.content, optimized);
+        assert_eq!(versions[0].version.style, "code-gen");
+        assert_eq!(versions[0].version.provider, "claude");
+        assert_eq!(versions[1].version.content, "draft");
+
+        let versions_cli = Cli::parse_from(vec!["inkspect", "versions", file.to_str().unwrap()]);
+        run(versions_cli, config.clone(), &mock_backend)
+            .await
+            .unwrap();
+
+        let undo = |args: &[&str]| {
+            let mut cli = vec!["inkspect", "undo"];
+            cli.extend(args);
+            Cli::parse_from(cli)
+        };
+        let contents = || {
+            store
+                .versions(&file)
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.version.content, entry.version.style))
+                .collect::<Vec<_>>()
+        };
+        run(
+            undo(&[file.to_str().unwrap()]),
+            config.clone(),
+            &mock_backend,
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), optimized);
+        // The content the undo overwrote is kept as the newest version.
+        assert_eq!(
+            contents(),
+            vec![
+                ("Mocked response".to_string(), "undo".to_string()),
+                ("draft".to_string(), "code-gen".to_string()),
+            ]
+        );
+
+        // Undoing again undoes the undo.
+        run(undo(&[]), config.clone(), &mock_backend).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "Mocked response");
+        assert_eq!(
+            contents(),
+            vec![
+                (optimized.clone(), "undo".to_string()),
+                ("draft".to_string(), "code-gen".to_string()),
+            ]
+        );
+
+        run(undo(&["--to", "2"]), config.clone(), &mock_backend)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "draft");
+        assert_eq!(
+            contents(),
+            vec![
+                ("Mocked response".to_string(), "undo".to_string()),
+                (optimized, "undo".to_string()),
+            ]
+        );
+
+        let err = run(undo(&["--to", "3"]), config.clone(), &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, format!("No version 3 of '{}'", file.display()));
+        config.backups.dir = Some(dir.path().join("empty"));
+        let err = run(undo(&[]), config, &mock_backend)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "No saved version of any file");
+    }
+
+    /// Answers with the input uppercased, and fails on inputs saying FAIL.
+    fn uppercase_backend() -> ScriptedLlmBackend {
+        ScriptedLlmBackend::respond(|request| {
+            let input = request.prompt.lines().last().unwrap_or_default();
+            if input.contains("FAIL") {
+                return Err(LlmError::Unavailable("overloaded".to_string()).into());
+            }
+            Ok(input.to_uppercase())
+        })
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_output_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let prompts = dir.path().join("prompts");
+        std::fs::create_dir_all(prompts.join("nested")).unwrap();
+        std::fs::write(prompts.join("a.md"), "first").unwrap();
+        std::fs::write(prompts.join("nested/b.md"), "second").unwrap();
+        std::fs::write(prompts.join("c.md"), "FAIL").unwrap();
+        let out = dir.path().join("out");
+
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "batch",
+            "--output-dir",
+            out.to_str().unwrap(),
+            prompts.to_str().unwrap(),
+        ]);
+        let err = run(cli, Config::default(), &uppercase_backend().factory())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "1 of 3 files failed");
+
+        assert_eq!(std::fs::read_to_string(out.join("a.md")).unwrap(), "FIRST");
+        assert_eq!(
+            std::fs::read_to_string(out.join("nested/b.md")).unwrap(),
+            "SECOND"
+        );
+        assert!(!out.join("c.md").exists());
+        assert_eq!(
+            std::fs::read_to_string(prompts.join("a.md")).unwrap(),
+            "first"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_fail_fast_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("1.md"), "FAIL").unwrap();
+        std::fs::write(dir.path().join("2.md"), "second").unwrap();
+
+        let pattern = dir.path().join("*.md");
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "batch",
+            "--in-place",
+            "--fail-fast",
+            "--jobs",
+            "1",
+            pattern.to_str().unwrap(),
+        ]);
+        let err = run(cli, Config::default(), &uppercase_backend().factory())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 files failed");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("2.md")).unwrap(),
+            "second"
+        );
+
+        std::fs::write(dir.path().join("1.md"), "first").unwrap();
+        let cli = Cli::parse_from(vec![
+            "inkspect",
+            "batch",
+            "--in-place",
+            pattern.to_str().unwrap(),
+        ]);
+        run(cli, Config::default(), &uppercase_backend().factory())
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("1.md")).unwrap(),
+            "FIRST"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("2.md")).unwrap(),
+            "SECOND"
+        );
+    }
 }
@@ -0,0 +1,93 @@
+use console::style;
+use similar::TextDiff;
+use std::fs;
+use std::path::Path;
+
+/// A unified diff from `original` to `updated`, with git-style `a/` and `b/`
+/// headers so that it applies with `git apply` or `patch -p1`. Empty when
+/// nothing changed.
+pub fn unified(original: &str, updated: &str, path: &str) -> String {
+    TextDiff::from_lines(original, updated)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+/// How a patch names `path`: relative to the current directory, with `/`
+/// separators and no `.` components, as `git apply` expects. `None` when the
+/// file is outside the current directory or does not exist.
+pub fn patch_path(path: &Path) -> Option<String> {
+    let path = fs::canonicalize(path).ok()?;
+    let current_dir = std::env::current_dir().and_then(fs::canonicalize).ok()?;
+    let relative = path.strip_prefix(current_dir).ok()?;
+    Some(
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Colours a unified diff for the terminal: removed lines in red, added
+/// lines in green and hunk headers in cyan.
+pub fn colorize(diff: &str) -> String {
+    diff.lines()
+        .map(|line| {
+            if line.starts_with("---") || line.starts_with("+++") {
+                style(line).bold().to_string()
+            } else if line.starts_with('-') {
+                style(line).red().to_string()
+            } else if line.starts_with('+') {
+                style(line).green().to_string()
+            } else if line.starts_with("@@") {
+                style(line).cyan().to_string()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified() {
+        let diff = unified("one\ntwo\nthree\n", "one\n2\nthree\n", "notes.md");
+        assert_eq!(
+            diff,
+            "--- a/notes.md\n+++ b/notes.md\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"
+        );
+        assert_eq!(unified("same\n", "same\n", "notes.md"), "");
+    }
+
+    #[test]
+    fn test_patch_path() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let name = dir.path().file_name().unwrap().to_str().unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/notes.md"), "").unwrap();
+        let expected = Some(format!("{}/docs/notes.md", name));
+
+        let dotted = format!("./{}/./docs/notes.md", name);
+        assert_eq!(patch_path(Path::new(&dotted)), expected);
+        let absolute = std::fs::canonicalize(dir.path())
+            .unwrap()
+            .join("docs/notes.md");
+        assert_eq!(patch_path(&absolute), expected);
+
+        let outside = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(patch_path(outside.path()), None);
+        assert_eq!(patch_path(Path::new("missing.md")), None);
+    }
+
+    #[test]
+    fn test_colorize_keeps_lines() {
+        let diff = unified("a\n", "b\n", "x");
+        assert_eq!(console::strip_ansi_codes(&colorize(&diff)), diff.trim_end());
+    }
+}
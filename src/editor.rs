@@ -1,15 +1,114 @@
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io::Result;
-use std::path::Path;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// Lines starting with this are dropped from the editor buffer. Not `#`, as
+/// in git, since that would drop Markdown headings.
+pub const DEFAULT_COMMENT_MARKER: &str = ";";
+
+/// How the buffer opened for an input typed in the editor is prepared.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EditorConfig {
+    /// Lines starting with this marker are ignored. An empty marker keeps
+    /// every line.
+    #[serde(default = "default_comment_marker")]
+    pub comment_marker: String,
+    /// Whether the buffer also shows, commented out, the previous input.
+    #[serde(default)]
+    pub previous_input: bool,
+    /// Where the previous input is kept. Set at startup, not configurable.
+    #[serde(skip)]
+    pub history_file: Option<PathBuf>,
+}
+
+fn default_comment_marker() -> String {
+    DEFAULT_COMMENT_MARKER.to_string()
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        Self {
+            comment_marker: default_comment_marker(),
+            previous_input: false,
+            history_file: None,
+        }
+    }
+}
+
+/// The default location of the previous input, under the platform's data
+/// directory, e.g. `~/.local/share/inkspect/last-input.md`.
+pub fn default_history_file() -> Option<PathBuf> {
+    directories::ProjectDirs::from("rs", "", "inkspect")
+        .map(|dirs| dirs.data_dir().join("last-input.md"))
+}
+
+/// Runs the editor on `file_path`. An editor quitting with an error, as vim
+/// does on `:cq`, is reported as a failure.
 pub fn open_editor(file_path: &Path, editor: &str) -> Result<()> {
-    Command::new(editor).arg(file_path).status()?;
+    let status = Command::new(editor).arg(file_path).status()?;
+    if !status.success() {
+        return Err(Error::other(format!(
+            "The editor '{}' exited with {}",
+            editor, status
+        )));
+    }
     Ok(())
 }
 
-pub fn read_editor_input(file_path: &Path) -> Result<String> {
-    fs::read_to_string(file_path)
+/// Reads the buffer back, without the lines starting with `comment_marker`
+/// and the blank lines left at the end where they were.
+pub fn read_editor_input(file_path: &Path, comment_marker: &str) -> Result<String> {
+    let content = fs::read_to_string(file_path)?;
+    if comment_marker.is_empty() {
+        return Ok(content);
+    }
+    let mut stripped = false;
+    let mut kept = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        if line.starts_with(comment_marker) {
+            stripped = true;
+        } else {
+            kept.push_str(line);
+        }
+    }
+    if stripped {
+        while kept.ends_with("\n\n") || kept == "\n" {
+            kept.pop();
+        }
+    }
+    Ok(kept)
+}
+
+/// Turns each line of `text` into a comment.
+pub fn comment(text: &str, comment_marker: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                format!("{}\n", comment_marker)
+            } else {
+                format!("{} {}\n", comment_marker, line)
+            }
+        })
+        .collect()
+}
+
+/// The editor to run: the one given on the command line, else `$EDITOR`,
+/// else vim.
+pub fn command(editor: Option<String>) -> String {
+    editor
+        .or_else(|| std::env::var("EDITOR").ok())
+        .unwrap_or_else(|| "vim".to_string())
+}
+
+/// Opens `text` in the editor and returns the buffer once the editor exits.
+/// The temp file has a `.md` suffix so that editors highlight the Markdown.
+pub fn edit_text(text: &str, editor: &str) -> Result<String> {
+    let temp_file = tempfile::Builder::new().suffix(".md").tempfile()?;
+    fs::write(temp_file.path(), text)?;
+    open_editor(temp_file.path(), editor)?;
+    fs::read_to_string(temp_file.path())
 }
 
 #[cfg(test)]
@@ -35,7 +134,48 @@ mod tests {
         let file_path = dir.path().join("test_read_input.txt");
         fs::write(&file_path, "test content").unwrap();
 
-        let content = read_editor_input(&file_path).unwrap();
+        let content = read_editor_input(&file_path, ";").unwrap();
         assert_eq!(content, "test content");
     }
+
+    #[test]
+    fn test_read_editor_input_strips_comments() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("input.md");
+        let guidance = comment("Style: refine\n\nProvider: claude", ";");
+        assert_eq!(guidance, "; Style: refine\n;\n; Provider: claude\n");
+        fs::write(
+            &file_path,
+            format!("# Title\n\nBuild a todo app.\n\n{}", guidance),
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_editor_input(&file_path, ";").unwrap(),
+            "# Title\n\nBuild a todo app.\n"
+        );
+        assert_eq!(
+            read_editor_input(&file_path, "#").unwrap(),
+            "\nBuild a todo app.\n\n; Style: refine\n;\n; Provider: claude\n"
+        );
+        // Only the guidance: nothing to send.
+        fs::write(&file_path, format!("\n{}", guidance)).unwrap();
+        assert_eq!(read_editor_input(&file_path, ";").unwrap(), "");
+        // An empty marker keeps everything.
+        assert_eq!(
+            read_editor_input(&file_path, "").unwrap(),
+            format!("\n{}", guidance)
+        );
+    }
+
+    #[test]
+    fn test_edit_text() {
+        // `true` leaves the buffer as it was.
+        assert_eq!(edit_text("draft", "true").unwrap(), "draft");
+        assert_eq!(
+            edit_text("draft", "false").unwrap_err().to_string(),
+            "The editor 'false' exited with exit status: 1"
+        );
+        assert!(edit_text("draft", "/nonexistent/editor").is_err());
+    }
 }
@@ -0,0 +1,307 @@
+use crate::postprocess::{self, CodeBlock};
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+
+/// What to do when a code block targets a file that already exists.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Refuse to write anything if any target exists.
+    #[default]
+    Fail,
+    /// Leave existing files untouched and write the others.
+    Skip,
+    /// Replace existing files.
+    Always,
+}
+
+/// What happens, or would happen on a dry run, to a single file.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create,
+    Overwrite,
+    Skip,
+    /// The file exists and the policy is [`OverwritePolicy::Fail`].
+    Conflict,
+}
+
+impl Action {
+    pub fn describe(self, dry_run: bool) -> &'static str {
+        match (self, dry_run) {
+            (Action::Create, false) => "created",
+            (Action::Create, true) => "would create",
+            (Action::Overwrite, false) => "overwritten",
+            (Action::Overwrite, true) => "would overwrite",
+            (Action::Skip, _) => "skipped, already exists",
+            (Action::Conflict, _) => "already exists",
+        }
+    }
+}
+
+/// A code block and the file it goes to.
+#[derive(Serialize, Debug)]
+pub struct ExtractedFile {
+    /// Path relative to the target directory.
+    pub path: PathBuf,
+    pub lines: usize,
+    pub action: Action,
+}
+
+/// The usual file extension for a fence language, used to name blocks that
+/// carry no filename hint.
+fn extension(language: &str) -> &str {
+    match language.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" => "js",
+        "typescript" | "ts" => "ts",
+        "shell" | "sh" | "bash" | "zsh" => "sh",
+        "markdown" | "md" => "md",
+        "yaml" | "yml" => "yml",
+        "toml" => "toml",
+        "json" => "json",
+        "html" => "html",
+        "css" => "css",
+        "go" => "go",
+        "java" => "java",
+        "c" => "c",
+        "cpp" | "c++" => "cpp",
+        "ruby" | "rb" => "rb",
+        "sql" => "sql",
+        _ => "txt",
+    }
+}
+
+/// The filename hint of a block, from ```` ```rust src/main.rs ````,
+/// ```` ```rust:src/main.rs ```` or ```` ```rust title="src/main.rs" ````.
+fn filename_hint(block: &CodeBlock) -> Option<String> {
+    let rest = block.info[block.language.len()..].trim_start_matches(':');
+    let token = rest.split_whitespace().next()?;
+    let token = token
+        .split_once('=')
+        .map_or(token, |(_, value)| value)
+        .trim_matches(|c| c == '"' || c == '\'');
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+/// Checks that `path` stays inside the target directory: no absolute paths,
+/// no `..` and no drive prefixes.
+fn check_relative(path: &str) -> Result<PathBuf> {
+    let relative = PathBuf::from(path);
+    let escapes = relative.components().any(|component| {
+        matches!(
+            component,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_)
+        )
+    });
+    if escapes || relative.as_os_str().is_empty() {
+        return Err(anyhow::anyhow!(
+            "Refusing to write '{}': the path escapes the target directory",
+            path
+        ));
+    }
+    Ok(relative)
+}
+
+/// Writes every fenced code block of `markdown` into `dir`, or only reports
+/// what would be written when `dry_run` is set. Nothing is written unless
+/// every block maps to a valid path and no conflict blocks the whole batch.
+pub fn extract(
+    markdown: &str,
+    dir: &Path,
+    policy: OverwritePolicy,
+    dry_run: bool,
+) -> Result<Vec<ExtractedFile>> {
+    let blocks = postprocess::code_blocks(markdown);
+    if blocks.is_empty() {
+        return Err(anyhow::anyhow!("No code blocks found in the response"));
+    }
+
+    let mut planned: Vec<(ExtractedFile, String)> = Vec::new();
+    for (index, block) in blocks.into_iter().enumerate() {
+        let path = match filename_hint(&block) {
+            Some(hint) => check_relative(&hint)?,
+            None => PathBuf::from(format!(
+                "block-{}.{}",
+                index + 1,
+                extension(&block.language)
+            )),
+        };
+        if planned.iter().any(|(file, _)| file.path == path) {
+            return Err(anyhow::anyhow!(
+                "Several code blocks target '{}'",
+                path.display()
+            ));
+        }
+        let action = match (dir.join(&path).exists(), policy) {
+            (false, _) => Action::Create,
+            (true, OverwritePolicy::Always) => Action::Overwrite,
+            (true, OverwritePolicy::Skip) => Action::Skip,
+            (true, OverwritePolicy::Fail) => Action::Conflict,
+        };
+        planned.push((
+            ExtractedFile {
+                path,
+                lines: block.content.lines().count(),
+                action,
+            },
+            block.content,
+        ));
+    }
+
+    if dry_run {
+        return Ok(planned.into_iter().map(|(file, _)| file).collect());
+    }
+
+    let conflicts: Vec<String> = planned
+        .iter()
+        .filter(|(file, _)| file.action == Action::Conflict)
+        .map(|(file, _)| file.path.display().to_string())
+        .collect();
+    if !conflicts.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Refusing to overwrite existing files: {}. Use --overwrite skip or --overwrite always",
+            conflicts.join(", ")
+        ));
+    }
+
+    for (file, content) in &planned {
+        if file.action == Action::Skip {
+            continue;
+        }
+        let target = dir.join(&file.path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&target, content)
+            .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", target.display(), e))?;
+        log::debug!("Wrote {}", target.display());
+    }
+    Ok(planned.into_iter().map(|(file, _)| file).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE: &str = "Here is the project.\n\n```rust src/main.rs\nfn main() {\n    println!(\"hi\");\n}\n```\n\n```toml:Cargo.toml\n[package]\nname = \"hi\"\n```\n\n```sh\ncargo run\n```\n";
+
+    fn paths(files: &[ExtractedFile]) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| f.path.to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_writes_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = extract(RESPONSE, dir.path(), OverwritePolicy::Fail, false).unwrap();
+        assert_eq!(
+            paths(&files),
+            vec!["src/main.rs", "Cargo.toml", "block-3.sh"]
+        );
+        assert!(files.iter().all(|f| f.action == Action::Create));
+        assert_eq!(files[0].lines, 3);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("src/main.rs")).unwrap(),
+            "fn main() {\n    println!(\"hi\");\n}\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("block-3.sh")).unwrap(),
+            "cargo run\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_dry_run_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "old").unwrap();
+        let files = extract(RESPONSE, dir.path(), OverwritePolicy::Fail, true).unwrap();
+        assert_eq!(
+            files.iter().map(|f| f.action).collect::<Vec<_>>(),
+            vec![Action::Create, Action::Conflict, Action::Create]
+        );
+        assert!(!dir.path().join("src").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("Cargo.toml")).unwrap(),
+            "old"
+        );
+    }
+
+    #[test]
+    fn test_extract_overwrite_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "old").unwrap();
+
+        let err = extract(RESPONSE, dir.path(), OverwritePolicy::Fail, false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Refusing to overwrite existing files: Cargo.toml"));
+        assert!(!dir.path().join("src/main.rs").exists());
+
+        let files = extract(RESPONSE, dir.path(), OverwritePolicy::Skip, false).unwrap();
+        assert_eq!(files[1].action, Action::Skip);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("Cargo.toml")).unwrap(),
+            "old"
+        );
+        assert!(dir.path().join("src/main.rs").exists());
+
+        let files = extract(RESPONSE, dir.path(), OverwritePolicy::Always, false).unwrap();
+        assert_eq!(files[0].action, Action::Overwrite);
+        assert!(
+            std::fs::read_to_string(dir.path().join("Cargo.toml"))
+                .unwrap()
+                .starts_with("[package]")
+        );
+    }
+
+    #[test]
+    fn test_extract_refuses_escaping_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        for hint in ["../evil.sh", "/etc/passwd", "src/../../evil.sh"] {
+            let markdown = format!("```sh {}\nrm -rf /\n```\n", hint);
+            let err = extract(&markdown, dir.path(), OverwritePolicy::Always, false)
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("escapes the target directory"), "{}", hint);
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_extract_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = extract("No code here.", dir.path(), OverwritePolicy::Fail, false)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "No code blocks found in the response");
+
+        let markdown = "```rust a.rs\n1\n```\n```rust a.rs\n2\n```\n";
+        let err = extract(markdown, dir.path(), OverwritePolicy::Fail, false)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Several code blocks target 'a.rs'");
+    }
+
+    #[test]
+    fn test_filename_hint_forms() {
+        let hint = |markdown: &str| filename_hint(&postprocess::code_blocks(markdown)[0]);
+        assert_eq!(
+            hint("```rust src/lib.rs\n```"),
+            Some("src/lib.rs".to_string())
+        );
+        assert_eq!(
+            hint("```rust:src/lib.rs\n```"),
+            Some("src/lib.rs".to_string())
+        );
+        assert_eq!(
+            hint("```python title=\"app.py\"\n```"),
+            Some("app.py".to_string())
+        );
+        assert_eq!(hint("```rust\n```"), None);
+        assert_eq!(hint("```\n```"), None);
+    }
+}
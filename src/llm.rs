@@ -1,3 +1,9 @@
 pub mod claude;
+pub mod error;
+pub mod factory;
 pub mod gemini;
+pub mod ollama;
+pub mod openai;
+pub mod retry;
+pub mod sse;
 pub mod r#trait;
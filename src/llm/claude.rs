@@ -1,25 +1,126 @@
-use super::r#trait::LlmBackend;
+use super::error::{LlmError, ProviderApi};
+use super::retry::{self, RetryPolicy};
+use super::sse;
+use super::r#trait::{LlmBackend, LlmRequest, LlmResponse, TextStream, Usage};
 use anyhow::Result;
-use reqwest::Client;
+use futures_util::TryStreamExt;
+use reqwest::{Client, RequestBuilder, StatusCode};
 use serde::{Deserialize, Serialize};
 
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+/// The tool Claude is forced to call when the response must follow a schema.
+const RESPONSE_TOOL: &str = "respond";
+
 pub struct ClaudeBackend {
     api_key: String,
     client: Client,
     url: String,
     model: String,
+    retry: RetryPolicy,
 }
 
 #[derive(Serialize)]
 struct ClaudeRequest {
-    prompt: String,
     model: String,
-    max_tokens_to_sample: u32,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    name: &'static str,
+    description: &'static str,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ToolChoice {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'static str,
+}
+
+#[derive(Serialize)]
+struct Message {
+    role: String,
+    content: String,
 }
 
 #[derive(Deserialize)]
 struct ClaudeResponse {
-    completion: String,
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+    /// The arguments of a `tool_use` block.
+    input: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct StreamEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    delta: Option<StreamDelta>,
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+struct StreamDelta {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    text: String,
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ApiError,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<Model>,
+}
+
+#[derive(Deserialize)]
+struct Model {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ClaudeUsage {
+    input_tokens: u64,
+    output_tokens: u64,
 }
 
 impl ClaudeBackend {
@@ -29,79 +130,464 @@ impl ClaudeBackend {
             client: Client::new(),
             url: "https://api.anthropic.com".to_string(),
             model,
+            retry: RetryPolicy::default(),
         }
     }
 
-    #[cfg(test)]
     pub fn new_with_url(api_key: String, url: String, model: String) -> Self {
         Self {
             api_key,
             client: Client::new(),
             url,
             model,
+            retry: RetryPolicy::default(),
         }
     }
-}
 
-#[async_trait::async_trait]
-impl LlmBackend for ClaudeBackend {
-    async fn request(&self, full_prompt: &str) -> Result<String> {
-        let full_url = format!("{}/v1/complete", self.url);
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
+    fn post(&self, request: &LlmRequest, stream: bool) -> RequestBuilder {
+        let full_url = format!("{}/v1/messages", self.url);
+
+        let params = &request.params;
+        if params.seed.is_some() {
+            log::debug!("Claude does not support seeds, ignoring it");
+        }
         let request_body = ClaudeRequest {
-            prompt: full_prompt.to_string(),
             model: self.model.clone(),
-            max_tokens_to_sample: 300,
+            max_tokens: params.max_output_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            system: request.system_prompt.clone(),
+            messages: request
+                .messages()
+                .into_iter()
+                .map(|message| Message {
+                    role: message.role.as_str().to_string(),
+                    content: message.content,
+                })
+                .collect(),
+            temperature: params.temperature,
+            top_p: params.top_p,
+            stop_sequences: params.stop_sequences.clone(),
+            tools: request
+                .json_schema
+                .iter()
+                .map(|schema| Tool {
+                    name: RESPONSE_TOOL,
+                    description: "Returns the response as a JSON document.",
+                    input_schema: schema.clone(),
+                })
+                .collect(),
+            tool_choice: request.json_schema.as_ref().map(|_| ToolChoice {
+                kind: "tool",
+                name: RESPONSE_TOOL,
+            }),
+            stream,
         };
 
-        let response = self
-            .client
+        self.client
             .post(&full_url)
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("User-Agent", "inkspect/0.1.0")
             .json(&request_body)
-            .send()
-            .await?;
-        let response_text = response.text().await?;
-        if response_text.is_empty() {
-            return Err(anyhow::anyhow!("Empty response from Claude API"));
+    }
+}
+
+const API: ProviderApi = ProviderApi {
+    name: "Claude API",
+    classify: classify_body,
+};
+
+/// Maps an error object returned by the Messages API onto an [`LlmError`].
+fn classify(status: Option<StatusCode>, error: ApiError) -> LlmError {
+    let message = format!("Claude API Error: {}", error.message);
+    match error.kind.as_str() {
+        "authentication_error" | "permission_error" => LlmError::Auth(message),
+        "rate_limit_error" => LlmError::RateLimited(message),
+        "overloaded_error" | "api_error" => LlmError::Unavailable(message),
+        "request_too_large" => LlmError::ContextTooLong(message),
+        "invalid_request_error" if error.message.contains("prompt is too long") => {
+            LlmError::ContextTooLong(message)
         }
-        log::debug!("Claude API response: {}", response_text);
+        _ => match status {
+            Some(status) => LlmError::from_status(status, message),
+            None => LlmError::Api(message),
+        },
+    }
+}
+
+/// Classifies the error object of a response body, if it has one.
+fn classify_body(status: StatusCode, json_value: &serde_json::Value) -> Option<LlmError> {
+    let ErrorBody { error } = ErrorBody::deserialize(json_value).ok()?;
+    Some(classify(Some(status), error))
+}
 
-        let json_value: serde_json::Value = serde_json::from_str(&response_text)?;
+/// Fails on stop reasons that leave the answer unusable.
+fn check_stop_reason(stop_reason: Option<&str>) -> Result<(), LlmError> {
+    match stop_reason {
+        Some("max_tokens") => Err(LlmError::Truncated(
+            "Claude API Error: the response was truncated at the max_tokens limit".to_string(),
+        )),
+        Some("refusal") => Err(LlmError::ContentBlocked(
+            "Claude API Error: the model declined to answer".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
 
-        if let Some(error) = json_value.get("error") {
-            if let Some(message) = error.get("message") {
-                return Err(anyhow::anyhow!(
-                    "Claude API Error: {}",
-                    message.as_str().unwrap_or("Unknown error")
-                ));
-            }
+/// Extracts the text carried by a single Messages streaming event, if any.
+fn stream_delta(event: sse::SseEvent) -> Result<Option<String>> {
+    let event: StreamEvent = serde_json::from_str(&event.data).map_err(|e| API.malformed(e))?;
+    match event.kind.as_str() {
+        "content_block_delta" => Ok(event
+            .delta
+            .filter(|delta| delta.kind == "text_delta")
+            .map(|delta| delta.text)),
+        "message_delta" => {
+            check_stop_reason(event.delta.and_then(|d| d.stop_reason).as_deref())?;
+            Ok(None)
+        }
+        "error" => Err(match event.error {
+            Some(error) => classify(None, error),
+            None => LlmError::Api("Claude API Error: Unknown error".to_string()),
         }
+        .into()),
+        _ => Ok(None),
+    }
+}
 
-        let claude_response: ClaudeResponse = serde_json::from_value(json_value)?;
-        Ok(claude_response.completion)
+#[async_trait::async_trait]
+impl LlmBackend for ClaudeBackend {
+    async fn request(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let response = retry::send(&self.retry, || self.post(request, false)).await?;
+        let claude_response: ClaudeResponse = API.read_json(response).await?;
+        check_stop_reason(claude_response.stop_reason.as_deref())?;
+        // With a schema, the answer is the input of the forced tool call.
+        let tool_input = claude_response
+            .content
+            .iter()
+            .find(|block| block.kind == "tool_use")
+            .and_then(|block| block.input.as_ref());
+        let text = match tool_input {
+            Some(input) if request.json_schema.is_some() => input.to_string(),
+            _ => claude_response
+                .content
+                .iter()
+                .filter(|block| block.kind == "text")
+                .map(|block| block.text.as_str())
+                .collect::<String>(),
+        };
+
+        Ok(LlmResponse {
+            text,
+            stop_reason: claude_response.stop_reason,
+            usage: claude_response.usage.map(|u| Usage {
+                input_tokens: u.input_tokens,
+                output_tokens: u.output_tokens,
+            }),
+        })
     }
 
     async fn list_models(&self) -> Result<Vec<String>> {
-        Ok(vec!["claude-2".to_string(), "claude-3".to_string()])
+        let full_url = format!("{}/v1/models?limit=1000", self.url);
+        let response = self
+            .client
+            .get(&full_url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("User-Agent", "inkspect/0.1.0")
+            .send()
+            .await
+            .map_err(LlmError::from)?;
+        let models_response: ModelsResponse = API.read_json(response).await?;
+        Ok(models_response.data.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn stream(&self, request: &LlmRequest) -> Result<TextStream> {
+        let response = retry::send(&self.retry, || self.post(request, true)).await?;
+        let response = API.check_status(response).await?;
+
+        let deltas = sse::events(response.bytes_stream().map_err(LlmError::from))
+            .try_filter_map(|event| async move { stream_delta(event) });
+        Ok(Box::pin(deltas))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use mockito::Server;
+    use crate::llm::r#trait::{self, GenerationParams, Role};
+    use mockito::{Matcher, Server};
+
+    fn test_request() -> LlmRequest {
+        LlmRequest {
+            system_prompt: Some("Be direct.".to_string()),
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        }
+    }
 
     #[tokio::test]
     async fn test_claude_backend_request() {
         let mut server = Server::new_async().await;
         let mock = server
-            .mock("POST", "/v1/complete")
+            .mock("POST", "/v1/messages")
+            .match_header("x-api-key", "test_api_key")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "model": "claude-sonnet-4-20250514",
+                "system": "Be direct.",
+                "messages": [{"role": "user", "content": "test prompt"}],
+            })))
             .with_status(200)
             .with_header("content-type", "application/json")
-            .with_body(r#"{"completion":"Mocked Claude response"}"#)
+            .with_body(
+                r#"{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"text","text":"Mocked Claude response"}],"stop_reason":"end_turn","usage":{"input_tokens":12,"output_tokens":3}}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let response = backend.request(&test_request()).await.unwrap();
+        assert_eq!(response.text, "Mocked Claude response");
+        assert_eq!(response.stop_reason.as_deref(), Some("end_turn"));
+        assert_eq!(
+            response.usage,
+            Some(Usage {
+                input_tokens: 12,
+                output_tokens: 3
+            })
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_request_without_system_prompt() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_body(Matcher::Json(serde_json::json!({
+                "model": "claude-sonnet-4-20250514",
+                "max_tokens": DEFAULT_MAX_TOKENS,
+                "messages": [{"role": "user", "content": "test prompt"}],
+            })))
+            .with_status(200)
+            .with_body(r#"{"content":[{"type":"text","text":"ok"}],"stop_reason":"end_turn"}"#)
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let request = LlmRequest {
+            system_prompt: None,
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, "ok");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_request_generation_params() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "max_tokens": 16000,
+                "temperature": 0.5,
+                "stop_sequences": ["END"],
+            })))
+            .with_status(200)
+            .with_body(r#"{"content":[{"type":"text","text":"ok"}],"stop_reason":"end_turn"}"#)
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let request = LlmRequest {
+            prompt: "test prompt".to_string(),
+            params: GenerationParams {
+                temperature: Some(0.5),
+                max_output_tokens: Some(16000),
+                stop_sequences: Some(vec!["END".to_string()]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        backend.request(&request).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_request_json_schema() {
+        let mut server = Server::new_async().await;
+        let schema = serde_json::json!({"type": "object", "required": ["title"]});
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "tools": [{"name": "respond", "input_schema": schema}],
+                "tool_choice": {"type": "tool", "name": "respond"},
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{"content":[{"type":"tool_use","id":"toolu_1","name":"respond","input":{"title":"Fix login"}}],"stop_reason":"tool_use"}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let request = LlmRequest {
+            prompt: "test prompt".to_string(),
+            json_schema: Some(schema),
+            ..Default::default()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, r#"{"title":"Fix login"}"#);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_request_history() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "messages": [
+                    {"role": "user", "content": "Refine: a todo app"},
+                    {"role": "assistant", "content": "A todo app with tags."},
+                    {"role": "user", "content": "shorter"},
+                ],
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{"content":[{"type":"text","text":"A todo app."}],"stop_reason":"end_turn"}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let request = LlmRequest {
+            history: vec![
+                r#trait::Message {
+                    role: Role::User,
+                    content: "Refine: a todo app".to_string(),
+                },
+                r#trait::Message {
+                    role: Role::Assistant,
+                    content: "A todo app with tags.".to_string(),
+                },
+            ],
+            prompt: "shorter".to_string(),
+            ..Default::default()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, "A todo app.");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_request_multiple_blocks() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .with_status(200)
+            .with_body(
+                r#"{"content":[{"type":"text","text":"First part. "},{"type":"tool_use","id":"t1","name":"noop","input":{}},{"type":"text","text":"Second part."}],"stop_reason":"end_turn","usage":{"input_tokens":5,"output_tokens":300}}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let response = backend.request(&test_request()).await.unwrap();
+        assert_eq!(response.text, "First part. Second part.");
+        assert_eq!(response.stop_reason.as_deref(), Some("end_turn"));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_request_truncated() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/messages")
+            .with_status(200)
+            .with_body(
+                r#"{"content":[{"type":"text","text":"Half an ans"}],"stop_reason":"max_tokens","usage":{"input_tokens":5,"output_tokens":4096}}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let err = backend.request(&test_request()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::Truncated(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_request_invalid_key() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/messages")
+            .with_status(401)
+            .with_body(
+                r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "bad_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let err = backend.request(&test_request()).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LlmError>(),
+            Some(&LlmError::Auth(
+                "Claude API Error: invalid x-api-key".to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_request_error() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .with_status(400)
+            .with_body(
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"model: not found"}}"#,
+            )
             .create_async()
             .await;
 
@@ -110,8 +596,117 @@ mod tests {
             server.url(),
             "claude-2".to_string(),
         );
-        let response = backend.request("test prompt").await.unwrap();
-        assert_eq!(response, "Mocked Claude response");
+        let err = backend.request(&test_request()).await.unwrap_err();
+        assert_eq!(err.to_string(), "Claude API Error: model: not found");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_list_models() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/v1/models?limit=1000")
+            .match_header("x-api-key", "test_api_key")
+            .with_status(200)
+            .with_body(
+                r#"{"data":[{"type":"model","id":"claude-opus-4-20250514","display_name":"Claude Opus 4"},{"type":"model","id":"claude-sonnet-4-20250514","display_name":"Claude Sonnet 4"}],"has_more":false}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let models = backend.list_models().await.unwrap();
+        assert_eq!(
+            models,
+            vec!["claude-opus-4-20250514", "claude-sonnet-4-20250514"]
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_backend_stream() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "stream": true,
+                "system": "Be direct.",
+            })))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "event: message_start\n",
+                "data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"content\":[]}}\n\n",
+                "event: content_block_start\n",
+                "data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+                "event: ping\n",
+                "data: {\"type\":\"ping\"}\n\n",
+                "event: content_block_delta\n",
+                "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n",
+                "event: content_block_delta\n",
+                "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\", world\"}}\n\n",
+                "event: content_block_stop\n",
+                "data: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+                "event: message_delta\n",
+                "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":4}}\n\n",
+                "event: message_stop\n",
+                "data: {\"type\":\"message_stop\"}\n\n",
+            ))
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let deltas: Vec<String> = backend
+            .stream(&test_request())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(deltas, vec!["Hello", ", world"]);
         mock.assert_async().await;
     }
+
+    #[tokio::test]
+    async fn test_claude_backend_stream_error_event() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/messages")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "event: content_block_delta\n",
+                "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n",
+                "event: error\n",
+                "data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+            ))
+            .create_async()
+            .await;
+
+        let backend = ClaudeBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "claude-sonnet-4-20250514".to_string(),
+        );
+        let result: Result<Vec<String>> = backend
+            .stream(&test_request())
+            .await
+            .unwrap()
+            .try_collect()
+            .await;
+        let err = result.unwrap_err();
+        assert_eq!(err.to_string(), "Claude API Error: Overloaded");
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::Unavailable(_))
+        ));
+    }
 }
@@ -0,0 +1,238 @@
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+/// Why a backend request failed. Each variant maps to its own process exit
+/// code so that scripts can tell, say, a bad API key from an exhausted quota.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LlmError {
+    /// The API key is missing, invalid or lacks permission.
+    Auth(String),
+    /// The provider rejected the request because of rate limits or quota.
+    RateLimited(String),
+    /// The provider is overloaded or failed on its side (5xx).
+    Unavailable(String),
+    /// The provider could not be reached.
+    Network(String),
+    /// The prompt or the response was blocked by a safety filter.
+    ContentBlocked(String),
+    /// The prompt does not fit in the model's context window.
+    ContextTooLong(String),
+    /// The response was cut off because it hit the output token limit.
+    Truncated(String),
+    /// The provider answered with something that could not be understood.
+    MalformedResponse(String),
+    /// Any other error reported by the provider, such as an unknown model.
+    Api(String),
+    /// The response kept failing validation against the style's JSON Schema.
+    SchemaMismatch(String),
+}
+
+impl LlmError {
+    /// Classifies an error from its HTTP status alone, for responses whose
+    /// payload carries nothing more specific.
+    pub fn from_status(status: StatusCode, message: String) -> Self {
+        match status.as_u16() {
+            401 | 403 => Self::Auth(message),
+            413 => Self::ContextTooLong(message),
+            429 => Self::RateLimited(message),
+            500..=599 => Self::Unavailable(message),
+            _ => Self::Api(message),
+        }
+    }
+
+    /// The process exit code inkspect terminates with for this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Auth(_) => 10,
+            Self::RateLimited(_) => 11,
+            Self::Unavailable(_) => 12,
+            Self::Network(_) => 13,
+            Self::ContentBlocked(_) => 14,
+            Self::ContextTooLong(_) => 15,
+            Self::Truncated(_) => 16,
+            Self::MalformedResponse(_) => 17,
+            Self::Api(_) => 18,
+            Self::SchemaMismatch(_) => 19,
+        }
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auth(message)
+            | Self::RateLimited(message)
+            | Self::Unavailable(message)
+            | Self::Network(message)
+            | Self::ContentBlocked(message)
+            | Self::ContextTooLong(message)
+            | Self::Truncated(message)
+            | Self::MalformedResponse(message)
+            | Self::Api(message)
+            | Self::SchemaMismatch(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::Network(format!("Network error: {}", error))
+    }
+}
+
+/// How a provider's API reports errors. The backends share the handling of
+/// responses built on it and only differ in `classify`.
+pub struct ProviderApi {
+    /// Name used in error messages, e.g. "Claude API".
+    pub name: &'static str,
+    /// Classifies the error object a JSON body carries, if it carries one.
+    pub classify: fn(StatusCode, &serde_json::Value) -> Option<LlmError>,
+}
+
+impl ProviderApi {
+    /// Builds the error for a response with a non-success status.
+    pub fn http_error(&self, status: StatusCode, body: &str) -> LlmError {
+        serde_json::from_str(body)
+            .ok()
+            .and_then(|json_value| (self.classify)(status, &json_value))
+            .unwrap_or_else(|| {
+                LlmError::from_status(
+                    status,
+                    format!("{} Error: HTTP {}: {}", self.name, status, body.trim()),
+                )
+            })
+    }
+
+    /// Returns the error carried by a response, if any.
+    pub fn api_error(&self, status: StatusCode, body: &str) -> Option<LlmError> {
+        if !status.is_success() {
+            return Some(self.http_error(status, body));
+        }
+        let json_value: serde_json::Value = serde_json::from_str(body).ok()?;
+        (self.classify)(status, &json_value)
+    }
+
+    pub fn malformed(&self, error: impl fmt::Display) -> LlmError {
+        LlmError::MalformedResponse(format!("Malformed response from {}: {}", self.name, error))
+    }
+
+    /// Reads a response and parses its JSON body, failing with the error the
+    /// provider reported, if any.
+    pub async fn read_json<T: DeserializeOwned>(&self, response: Response) -> Result<T, LlmError> {
+        let status = response.status();
+        let response_text = response.text().await?;
+        log::debug!("{} response: {}", self.name, response_text);
+        if let Some(error) = self.api_error(status, &response_text) {
+            return Err(error);
+        }
+        if response_text.is_empty() {
+            return Err(self.malformed("empty response"));
+        }
+        serde_json::from_str(&response_text).map_err(|e| self.malformed(e))
+    }
+
+    /// Returns a response to be streamed, or the error its body describes
+    /// when its status is not a success.
+    pub async fn check_status(&self, response: Response) -> Result<Response, LlmError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let response_text = response.text().await?;
+        log::debug!("{} response: {}", self.name, response_text);
+        Err(self.http_error(status, &response_text))
+    }
+}
+
+/// Exit code for any error surfaced by inkspect: the `LlmError` code when a
+/// backend failed, 1 otherwise.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    error
+        .downcast_ref::<LlmError>()
+        .map_or(1, LlmError::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        let classify = |code: u16| {
+            LlmError::from_status(StatusCode::from_u16(code).unwrap(), "boom".to_string())
+        };
+        assert_eq!(classify(401), LlmError::Auth("boom".to_string()));
+        assert_eq!(classify(403), LlmError::Auth("boom".to_string()));
+        assert_eq!(classify(413), LlmError::ContextTooLong("boom".to_string()));
+        assert_eq!(classify(429), LlmError::RateLimited("boom".to_string()));
+        assert_eq!(classify(529), LlmError::Unavailable("boom".to_string()));
+        assert_eq!(classify(404), LlmError::Api("boom".to_string()));
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            LlmError::Auth(String::new()),
+            LlmError::RateLimited(String::new()),
+            LlmError::Unavailable(String::new()),
+            LlmError::Network(String::new()),
+            LlmError::ContentBlocked(String::new()),
+            LlmError::ContextTooLong(String::new()),
+            LlmError::Truncated(String::new()),
+            LlmError::MalformedResponse(String::new()),
+            LlmError::Api(String::new()),
+            LlmError::SchemaMismatch(String::new()),
+        ];
+        let mut codes: Vec<u8> = errors.iter().map(LlmError::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(codes.iter().all(|code| *code > 2));
+    }
+
+    #[test]
+    fn test_provider_api_errors() {
+        let api = ProviderApi {
+            name: "Test API",
+            classify: |status, json_value| {
+                let message = json_value.get("error")?.as_str()?;
+                Some(LlmError::from_status(
+                    status,
+                    format!("Test API Error: {}", message),
+                ))
+            },
+        };
+        assert_eq!(
+            api.http_error(StatusCode::UNAUTHORIZED, r#"{"error": "bad key"}"#),
+            LlmError::Auth("Test API Error: bad key".to_string())
+        );
+        assert_eq!(
+            api.http_error(StatusCode::BAD_GATEWAY, "<html>oops</html>\n"),
+            LlmError::Unavailable(
+                "Test API Error: HTTP 502 Bad Gateway: <html>oops</html>".to_string()
+            )
+        );
+        assert_eq!(
+            api.api_error(StatusCode::OK, r#"{"error": "no such model"}"#),
+            Some(LlmError::Api("Test API Error: no such model".to_string()))
+        );
+        assert_eq!(api.api_error(StatusCode::OK, r#"{"text": "hi"}"#), None);
+        assert_eq!(
+            api.malformed("empty response"),
+            LlmError::MalformedResponse(
+                "Malformed response from Test API: empty response".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_exit_code_through_context() {
+        let error = anyhow::Error::new(LlmError::Auth("bad key".to_string()))
+            .context("while optimizing prompt");
+        assert_eq!(exit_code(&error), 10);
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use super::claude::ClaudeBackend;
+use super::gemini::GeminiBackend;
+use super::ollama::{self, OllamaBackend};
+use super::openai::{self, OpenAiBackend};
+use super::retry::RetryPolicy;
+use super::r#trait::LlmBackend;
+use crate::config::{Provider, ProviderKind};
+use anyhow::Result;
+use std::time::Duration;
+
+/// Builds the backend described by the provider entry registered as `name`.
+pub fn create_backend(name: &str, provider: &Provider) -> Result<Box<dyn LlmBackend>> {
+    let mut retry = RetryPolicy::default();
+    if let Some(max_attempts) = provider.max_attempts {
+        retry.max_attempts = max_attempts.max(1);
+    }
+    if let Some(deadline) = provider.retry_deadline_secs {
+        retry.deadline = Duration::from_secs(deadline);
+    }
+
+    let backend: Box<dyn LlmBackend> = match provider.kind(name)? {
+        ProviderKind::Gemini => match &provider.base_url {
+            Some(url) => Box::new(
+                GeminiBackend::new_with_url(
+                    provider.api_key.clone(),
+                    url.trim_end_matches('/').to_string(),
+                    provider.model.clone(),
+                )
+                .with_retry_policy(retry),
+            ),
+            None => Box::new(
+                GeminiBackend::new(provider.api_key.clone(), provider.model.clone())
+                    .with_retry_policy(retry),
+            ),
+        },
+        ProviderKind::Claude => match &provider.base_url {
+            Some(url) => Box::new(
+                ClaudeBackend::new_with_url(
+                    provider.api_key.clone(),
+                    url.trim_end_matches('/').to_string(),
+                    provider.model.clone(),
+                )
+                .with_retry_policy(retry),
+            ),
+            None => Box::new(
+                ClaudeBackend::new(provider.api_key.clone(), provider.model.clone())
+                    .with_retry_policy(retry),
+            ),
+        },
+        ProviderKind::Openai => Box::new(
+            OpenAiBackend::new(
+                provider
+                    .base_url
+                    .clone()
+                    .unwrap_or_else(|| openai::DEFAULT_BASE_URL.to_string()),
+                Some(provider.api_key.clone()),
+                provider.model.clone(),
+            )
+            .with_retry_policy(retry),
+        ),
+        ProviderKind::Ollama => Box::new(
+            OllamaBackend::new(
+                provider
+                    .base_url
+                    .clone()
+                    .unwrap_or_else(|| ollama::DEFAULT_HOST.to_string()),
+                provider.model.clone(),
+            )
+            .with_retry_policy(retry),
+        ),
+    };
+    Ok(backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::r#trait::LlmRequest;
+    use mockito::Server;
+
+    #[tokio::test]
+    async fn test_create_backend_uses_entry_endpoint_and_credentials() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/messages")
+            .match_header("x-api-key", "WORK_KEY")
+            .with_status(200)
+            .with_body(r#"{"content":[{"type":"text","text":"from work"}]}"#)
+            .create_async()
+            .await;
+
+        let provider = Provider {
+            kind: Some(ProviderKind::Claude),
+            api_key: "WORK_KEY".to_string(),
+            model: "claude-opus-4-20250514".to_string(),
+            base_url: Some(format!("{}/", server.url())),
+            ..Default::default()
+        };
+        let backend = create_backend("work-claude", &provider).unwrap();
+        let response = backend.request(&LlmRequest::default()).await.unwrap();
+        assert_eq!(response.text, "from work");
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_create_backend_without_kind() {
+        let provider = Provider {
+            model: "model".to_string(),
+            ..Default::default()
+        };
+        assert!(create_backend("ollama", &provider).is_ok());
+        assert!(create_backend("somewhere", &provider).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_backend_applies_retry_settings() {
+        let mut server = Server::new_async().await;
+        let overloaded = server
+            .mock("POST", "/v1/messages")
+            .with_status(529)
+            .with_body(
+                r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+            )
+            .expect(2)
+            .create_async()
+            .await;
+
+        let provider = Provider {
+            kind: Some(ProviderKind::Claude),
+            model: "claude-sonnet-4-20250514".to_string(),
+            base_url: Some(server.url()),
+            max_attempts: Some(2),
+            ..Default::default()
+        };
+        let backend = create_backend("claude", &provider).unwrap();
+        let err = backend.request(&LlmRequest::default()).await.unwrap_err();
+        assert_eq!(err.to_string(), "Claude API Error: Overloaded");
+        overloaded.assert_async().await;
+    }
+}
@@ -1,6 +1,10 @@
-use super::r#trait::LlmBackend;
+use super::error::{LlmError, ProviderApi};
+use super::retry::{self, RetryPolicy};
+use super::sse;
+use super::r#trait::{LlmBackend, LlmRequest, LlmResponse, Role, TextStream, Usage};
 use anyhow::Result;
-use reqwest::Client;
+use futures_util::{StreamExt, TryStreamExt, stream};
+use reqwest::{Client, RequestBuilder, StatusCode};
 use serde::{Deserialize, Serialize};
 
 pub struct GeminiBackend {
@@ -8,12 +12,36 @@ pub struct GeminiBackend {
     client: Client,
     url: String,
     model: String,
+    retry: RetryPolicy,
 }
 
 #[derive(Serialize)]
 struct GeminiRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
     contents: Vec<Content>,
     safety_settings: Vec<SafetySetting>,
+    #[serde(rename = "generationConfig", skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GenerationConfig>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<serde_json::Value>,
 }
 
 #[derive(Serialize)]
@@ -24,6 +52,8 @@ struct SafetySetting {
 
 #[derive(Serialize)]
 struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
     parts: Vec<Part>,
 }
 
@@ -33,22 +63,59 @@ struct Part {
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiResponse {
+    #[serde(default)]
     candidates: Vec<Candidate>,
+    usage_metadata: Option<UsageMetadata>,
+    prompt_feedback: Option<PromptFeedback>,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ApiError,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    status: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Candidate {
+    #[serde(default)]
     content: ContentResponse,
+    finish_reason: Option<String>,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
+
+#[derive(Deserialize, Default)]
 struct ContentResponse {
+    #[serde(default)]
     parts: Vec<PartResponse>,
 }
 
 #[derive(Deserialize)]
 struct PartResponse {
+    #[serde(default)]
     text: String,
 }
 
@@ -59,34 +126,59 @@ impl GeminiBackend {
             client: Client::new(),
             url: "https://generativelanguage.googleapis.com".to_string(),
             model,
+            retry: RetryPolicy::default(),
         }
     }
 
-    #[cfg(test)]
     pub fn new_with_url(api_key: String, url: String, model: String) -> Self {
         Self {
             api_key,
             client: Client::new(),
             url,
             model,
+            retry: RetryPolicy::default(),
         }
     }
-}
 
-#[async_trait::async_trait]
-impl LlmBackend for GeminiBackend {
-    async fn request(&self, full_prompt: &str) -> Result<String> {
-        let full_url = format!(
-            "{}/v1beta/{}:generateContent?key={}",
-            self.url, self.model, self.api_key
-        );
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn post(&self, request: &LlmRequest, method: &str) -> RequestBuilder {
+        let full_url = if method == "streamGenerateContent" {
+            format!(
+                "{}/v1beta/{}:{}?alt=sse&key={}",
+                self.url, self.model, method, self.api_key
+            )
+        } else {
+            format!(
+                "{}/v1beta/{}:{}?key={}",
+                self.url, self.model, method, self.api_key
+            )
+        };
 
         let request_body = GeminiRequest {
-            contents: vec![Content {
+            system_instruction: request.system_prompt.as_ref().map(|system_prompt| Content {
+                role: None,
                 parts: vec![Part {
-                    text: full_prompt.to_string(),
+                    text: system_prompt.clone(),
                 }],
-            }],
+            }),
+            // Gemini calls the assistant's turns "model".
+            contents: request
+                .messages()
+                .into_iter()
+                .map(|message| Content {
+                    role: Some(match message.role {
+                        Role::User => "user",
+                        Role::Assistant => "model",
+                    }),
+                    parts: vec![Part {
+                        text: message.content,
+                    }],
+                })
+                .collect(),
             safety_settings: vec![
                 SafetySetting {
                     category: "HARM_CATEGORY_HARASSMENT".to_string(),
@@ -105,44 +197,195 @@ impl LlmBackend for GeminiBackend {
                     threshold: "BLOCK_NONE".to_string(),
                 },
             ],
+            generation_config: (!request.params.is_empty() || request.json_schema.is_some()).then(
+                || {
+                    let params = request.params.clone();
+                    GenerationConfig {
+                        temperature: params.temperature,
+                        top_p: params.top_p,
+                        max_output_tokens: params.max_output_tokens,
+                        stop_sequences: params.stop_sequences,
+                        seed: params.seed,
+                        response_mime_type: request
+                            .json_schema
+                            .as_ref()
+                            .map(|_| "application/json"),
+                        response_schema: request.json_schema.clone().map(response_schema),
+                    }
+                },
+            ),
         };
 
-        let response = self
-            .client
+        self.client
             .post(&full_url)
             .header("User-Agent", "inkspect/0.1.0")
             .json(&request_body)
-            .send()
-            .await?;
-        let response_text = response.text().await?;
-        if response_text.is_empty() {
-            return Err(anyhow::anyhow!("Empty response from Gemini API"));
+    }
+}
+
+/// Adapts a JSON Schema to the OpenAPI subset accepted by `responseSchema`
+/// by dropping the keywords Gemini rejects. The response is still validated
+/// against the full schema afterwards.
+fn response_schema(mut schema: serde_json::Value) -> serde_json::Value {
+    if let Some(object) = schema.as_object_mut() {
+        for keyword in ["$schema", "$id", "additionalProperties"] {
+            object.remove(keyword);
         }
-        log::debug!("Gemini API response: {}", response_text);
+        for value in object.values_mut() {
+            *value = response_schema(value.take());
+        }
+    } else if let Some(items) = schema.as_array_mut() {
+        for item in items {
+            *item = response_schema(item.take());
+        }
+    }
+    schema
+}
+
+const API: ProviderApi = ProviderApi {
+    name: "Gemini API",
+    classify: classify_body,
+};
+
+/// Maps an error object returned by the Gemini API onto an [`LlmError`].
+fn classify(status: StatusCode, error: ApiError) -> LlmError {
+    let message = format!("Gemini API Error: {}", error.message);
+    match error.status.as_str() {
+        "UNAUTHENTICATED" | "PERMISSION_DENIED" => LlmError::Auth(message),
+        "RESOURCE_EXHAUSTED" => LlmError::RateLimited(message),
+        "UNAVAILABLE" | "INTERNAL" => LlmError::Unavailable(message),
+        _ if error.message.contains("API key not valid") => LlmError::Auth(message),
+        _ if error
+            .message
+            .contains("exceeds the maximum number of tokens") =>
+        {
+            LlmError::ContextTooLong(message)
+        }
+        _ => LlmError::from_status(status, message),
+    }
+}
 
-        let json_value: serde_json::Value = serde_json::from_str(&response_text)?;
+/// Classifies the error object of a response body, if it has one.
+fn classify_body(status: StatusCode, json_value: &serde_json::Value) -> Option<LlmError> {
+    let ErrorBody { error } = ErrorBody::deserialize(json_value).ok()?;
+    Some(classify(status, error))
+}
 
-        if let Some(error) = json_value.get("error") {
-            if let Some(message) = error.get("message") {
-                return Err(anyhow::anyhow!(
-                    "Gemini API Error: {}",
-                    message.as_str().unwrap_or("Unknown error")
-                ));
-            }
+/// Fails when the prompt was blocked or the candidate finished for a reason
+/// that leaves the answer unusable.
+fn check_finish(
+    prompt_feedback: Option<&PromptFeedback>,
+    finish_reason: Option<&str>,
+) -> Result<(), LlmError> {
+    if let Some(reason) = prompt_feedback.and_then(|f| f.block_reason.as_deref()) {
+        return Err(LlmError::ContentBlocked(format!(
+            "Gemini API Error: the prompt was blocked ({})",
+            reason
+        )));
+    }
+    match finish_reason {
+        Some("MAX_TOKENS") => Err(LlmError::Truncated(
+            "Gemini API Error: the response was truncated at the max output tokens limit"
+                .to_string(),
+        )),
+        Some(reason @ ("SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII")) => {
+            Err(LlmError::ContentBlocked(format!(
+                "Gemini API Error: the response was blocked ({})",
+                reason
+            )))
         }
+        _ => Ok(()),
+    }
+}
+
+/// Extracts the text carried by a single `streamGenerateContent` chunk. A
+/// chunk may carry both the last piece of text and a failing finish reason.
+fn stream_chunk(event: sse::SseEvent) -> Vec<Result<String>> {
+    if let Some(error) = API.api_error(StatusCode::OK, &event.data) {
+        return vec![Err(error.into())];
+    }
+    let chunk: GeminiResponse = match serde_json::from_str(&event.data) {
+        Ok(chunk) => chunk,
+        Err(e) => return vec![Err(API.malformed(e).into())],
+    };
+    let candidate = chunk.candidates.into_iter().next();
+    let finish_reason = candidate.as_ref().and_then(|c| c.finish_reason.clone());
+
+    let mut items = Vec::new();
+    let text = candidate
+        .map(|candidate| {
+            candidate
+                .content
+                .parts
+                .into_iter()
+                .map(|part| part.text)
+                .collect::<String>()
+        })
+        .unwrap_or_default();
+    if !text.is_empty() {
+        items.push(Ok(text));
+    }
+    if let Err(error) = check_finish(chunk.prompt_feedback.as_ref(), finish_reason.as_deref()) {
+        items.push(Err(error.into()));
+    }
+    items
+}
+
+#[async_trait::async_trait]
+impl LlmBackend for GeminiBackend {
+    async fn request(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let response = retry::send(&self.retry, || self.post(request, "generateContent")).await?;
+        let gemini_response: GeminiResponse = API.read_json(response).await?;
+        check_finish(gemini_response.prompt_feedback.as_ref(), None)?;
+        let candidate = gemini_response
+            .candidates
+            .into_iter()
+            .next()
+            .ok_or_else(|| API.malformed("no candidates"))?;
+        check_finish(None, candidate.finish_reason.as_deref())?;
+        let text = candidate
+            .content
+            .parts
+            .into_iter()
+            .map(|part| part.text)
+            .collect::<String>();
 
-        let gemini_response: GeminiResponse = serde_json::from_value(json_value)?;
-        Ok(gemini_response.candidates[0].content.parts[0].text.clone())
+        Ok(LlmResponse {
+            text,
+            stop_reason: candidate.finish_reason,
+            usage: gemini_response.usage_metadata.map(|u| Usage {
+                input_tokens: u.prompt_token_count,
+                output_tokens: u.candidates_token_count,
+            }),
+        })
     }
 
     async fn list_models(&self) -> Result<Vec<String>> {
         let full_url = format!("{}/v1/models?key={}", self.url, self.api_key);
-        let response = self.client.get(&full_url).send().await?;
-        let response_text = response.text().await?;
-        log::debug!("Gemini API response: {}", response_text);
-        let models_response: ModelsResponse = serde_json::from_str(&response_text)?;
+        let response = self
+            .client
+            .get(&full_url)
+            .send()
+            .await
+            .map_err(LlmError::from)?;
+        let models_response: ModelsResponse = API.read_json(response).await?;
         Ok(models_response.models.into_iter().map(|m| m.name).collect())
     }
+
+    async fn stream(&self, request: &LlmRequest) -> Result<TextStream> {
+        let response =
+            retry::send(&self.retry, || self.post(request, "streamGenerateContent")).await?;
+        let response = API.check_status(response).await?;
+
+        let deltas =
+            sse::events(response.bytes_stream().map_err(LlmError::from)).flat_map(|event| {
+                stream::iter(match event {
+                    Ok(event) => stream_chunk(event),
+                    Err(e) => vec![Err(e)],
+                })
+            });
+        Ok(Box::pin(deltas))
+    }
 }
 
 #[derive(Deserialize)]
@@ -158,7 +401,8 @@ struct Model {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use mockito::Server;
+    use crate::llm::r#trait::{GenerationParams, Message};
+    use mockito::{Matcher, Server};
 
     #[tokio::test]
     async fn test_gemini_backend_request() {
@@ -168,10 +412,172 @@ mod tests {
                 "POST",
                 "/v1beta/gemini-2.5-pro:generateContent?key=test_api_key",
             )
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "system_instruction": {"parts": [{"text": "Be direct."}]},
+                "contents": [{"parts": [{"text": "test prompt"}]}],
+            })))
             .with_status(200)
             .with_header("content-type", "application/json")
             .with_body(
-                r#"{"candidates":[{"content":{"parts":[{"text":"Mocked Gemini response"}]}}]}"#,
+                r#"{"candidates":[{"content":{"parts":[{"text":"Mocked Gemini response"}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":7,"candidatesTokenCount":4}}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = GeminiBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "gemini-2.5-pro".to_string(),
+        );
+        let request = LlmRequest {
+            system_prompt: Some("Be direct.".to_string()),
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, "Mocked Gemini response");
+        assert_eq!(response.stop_reason.as_deref(), Some("STOP"));
+        assert_eq!(
+            response.usage,
+            Some(Usage {
+                input_tokens: 7,
+                output_tokens: 4
+            })
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_gemini_backend_request_generation_config() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock(
+                "POST",
+                "/v1beta/gemini-2.5-pro:generateContent?key=test_api_key",
+            )
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "generationConfig": {
+                    "topP": 0.9,
+                    "maxOutputTokens": 2048,
+                    "seed": 42,
+                },
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{"candidates":[{"content":{"parts":[{"text":"ok"}]},"finishReason":"STOP"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = GeminiBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "gemini-2.5-pro".to_string(),
+        );
+        let request = LlmRequest {
+            prompt: "test prompt".to_string(),
+            params: GenerationParams {
+                top_p: Some(0.9),
+                max_output_tokens: Some(2048),
+                seed: Some(42),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        backend.request(&request).await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_gemini_backend_request_history() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock(
+                "POST",
+                "/v1beta/gemini-2.5-pro:generateContent?key=test_api_key",
+            )
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "contents": [
+                    {"role": "user", "parts": [{"text": "Refine: a todo app"}]},
+                    {"role": "model", "parts": [{"text": "A todo app with tags."}]},
+                    {"role": "user", "parts": [{"text": "shorter"}]},
+                ],
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{"candidates":[{"content":{"parts":[{"text":"A todo app."}]},"finishReason":"STOP"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = GeminiBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "gemini-2.5-pro".to_string(),
+        );
+        let request = LlmRequest {
+            history: vec![
+                Message {
+                    role: Role::User,
+                    content: "Refine: a todo app".to_string(),
+                },
+                Message {
+                    role: Role::Assistant,
+                    content: "A todo app with tags.".to_string(),
+                },
+            ],
+            prompt: "shorter".to_string(),
+            ..Default::default()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, "A todo app.");
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_response_schema_drops_unsupported_keywords() {
+        let schema = serde_json::json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "steps": {
+                    "type": "array",
+                    "items": {"type": "object", "additionalProperties": false},
+                },
+            },
+            "additionalProperties": false,
+        });
+        assert_eq!(
+            response_schema(schema),
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "steps": {"type": "array", "items": {"type": "object"}},
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gemini_backend_request_response_schema() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock(
+                "POST",
+                "/v1beta/gemini-2.5-pro:generateContent?key=test_api_key",
+            )
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "generationConfig": {
+                    "responseMimeType": "application/json",
+                    "responseSchema": {
+                        "type": "object",
+                        "properties": {"tags": {"type": "array", "items": {"type": "string"}}},
+                    },
+                },
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{"candidates":[{"content":{"parts":[{"text":"{\"tags\":[]}"}]},"finishReason":"STOP"}]}"#,
             )
             .create_async()
             .await;
@@ -181,8 +587,156 @@ mod tests {
             server.url(),
             "gemini-2.5-pro".to_string(),
         );
-        let response = backend.request("test prompt").await.unwrap();
-        assert_eq!(response, "Mocked Gemini response");
+        let request = LlmRequest {
+            prompt: "test prompt".to_string(),
+            json_schema: Some(serde_json::json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "tags": {"type": "array", "items": {"type": "string"}}
+                },
+                "additionalProperties": false,
+            })),
+            ..Default::default()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, r#"{"tags":[]}"#);
         mock.assert_async().await;
     }
+
+    #[tokio::test]
+    async fn test_gemini_backend_stream() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock(
+                "POST",
+                "/v1beta/gemini-2.5-pro:streamGenerateContent?alt=sse&key=test_api_key",
+            )
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hello\"}],\"role\":\"model\"}}]}\r\n\r\n",
+                "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\", world\"}],\"role\":\"model\"},\"finishReason\":\"STOP\"}]}\r\n\r\n",
+                "data: {\"candidates\":[{\"finishReason\":\"STOP\"}],\"usageMetadata\":{\"promptTokenCount\":3}}\r\n\r\n",
+            ))
+            .create_async()
+            .await;
+
+        let backend = GeminiBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "gemini-2.5-pro".to_string(),
+        );
+        let request = LlmRequest {
+            system_prompt: None,
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        };
+        let deltas: Vec<String> = backend
+            .stream(&request)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(deltas, vec!["Hello", ", world"]);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_gemini_backend_stream_error_status() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock(
+                "POST",
+                "/v1beta/gemini-2.5-pro:streamGenerateContent?alt=sse&key=bad_key",
+            )
+            .with_status(400)
+            .with_body(r#"{"error":{"code":400,"message":"API key not valid.","status":"INVALID_ARGUMENT"}}"#)
+            .create_async()
+            .await;
+
+        let backend = GeminiBackend::new_with_url(
+            "bad_key".to_string(),
+            server.url(),
+            "gemini-2.5-pro".to_string(),
+        );
+        let request = LlmRequest {
+            system_prompt: None,
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        };
+        let err = backend.stream(&request).await.err().unwrap();
+        assert_eq!(err.to_string(), "Gemini API Error: API key not valid.");
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::Auth(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gemini_backend_request_blocked_prompt() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock(
+                "POST",
+                "/v1beta/gemini-2.5-pro:generateContent?key=test_api_key",
+            )
+            .with_status(200)
+            .with_body(r#"{"promptFeedback":{"blockReason":"SAFETY"}}"#)
+            .create_async()
+            .await;
+
+        let backend = GeminiBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "gemini-2.5-pro".to_string(),
+        );
+        let request = LlmRequest {
+            system_prompt: None,
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        };
+        let err = backend.request(&request).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::ContentBlocked(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gemini_backend_request_rate_limited() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock(
+                "POST",
+                "/v1beta/gemini-2.5-pro:generateContent?key=test_api_key",
+            )
+            .with_status(429)
+            .with_body(r#"{"error":{"code":429,"message":"Quota exceeded.","status":"RESOURCE_EXHAUSTED"}}"#)
+            .create_async()
+            .await;
+
+        let backend = GeminiBackend::new_with_url(
+            "test_api_key".to_string(),
+            server.url(),
+            "gemini-2.5-pro".to_string(),
+        )
+        .with_retry_policy(RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        });
+        let request = LlmRequest {
+            system_prompt: None,
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        };
+        let err = backend.request(&request).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LlmError>(),
+            Some(&LlmError::RateLimited(
+                "Gemini API Error: Quota exceeded.".to_string()
+            ))
+        );
+    }
 }
@@ -0,0 +1,407 @@
+use super::error::{LlmError, ProviderApi};
+use super::retry::{self, RetryPolicy};
+use super::r#trait::{LlmBackend, LlmRequest, LlmResponse, TextStream, Usage};
+use anyhow::Result;
+use futures_util::{Stream, StreamExt, TryStreamExt, stream};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_HOST: &str = "http://localhost:11434";
+
+/// A backend for a local Ollama server. Nothing leaves the machine and no
+/// API key is needed.
+pub struct OllamaBackend {
+    client: Client,
+    host: String,
+    model: String,
+    retry: RetryPolicy,
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<Options>,
+    /// JSON Schema the response is constrained to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    message: Option<ResponseMessage>,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    models: Vec<Model>,
+}
+
+#[derive(Deserialize)]
+struct Model {
+    name: String,
+}
+
+impl OllamaBackend {
+    pub fn new(host: String, model: String) -> Self {
+        Self {
+            client: Client::new(),
+            host: host.trim_end_matches('/').to_string(),
+            model,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn post(&self, request: &LlmRequest, stream: bool) -> RequestBuilder {
+        let full_url = format!("{}/api/chat", self.host);
+
+        let mut messages = Vec::new();
+        if let Some(system_prompt) = &request.system_prompt {
+            messages.push(Message {
+                role: "system".to_string(),
+                content: system_prompt.clone(),
+            });
+        }
+        messages.extend(request.messages().into_iter().map(|message| Message {
+            role: message.role.as_str().to_string(),
+            content: message.content,
+        }));
+
+        let request_body = ChatRequest {
+            model: self.model.clone(),
+            messages,
+            stream,
+            options: (!request.params.is_empty()).then(|| {
+                let params = request.params.clone();
+                Options {
+                    temperature: params.temperature,
+                    top_p: params.top_p,
+                    num_predict: params.max_output_tokens,
+                    stop: params.stop_sequences,
+                    seed: params.seed,
+                }
+            }),
+            format: request.json_schema.clone(),
+        };
+
+        self.client
+            .post(&full_url)
+            .header("User-Agent", "inkspect/0.1.0")
+            .json(&request_body)
+    }
+}
+
+const API: ProviderApi = ProviderApi {
+    name: "Ollama API",
+    classify,
+};
+
+/// Ollama reports errors as a plain `error` string.
+fn classify(status: StatusCode, json_value: &serde_json::Value) -> Option<LlmError> {
+    let message = json_value.get("error")?.as_str().unwrap_or("Unknown error");
+    Some(LlmError::from_status(
+        status,
+        format!("Ollama API Error: {}", message),
+    ))
+}
+
+/// Fails when generation stopped because it ran out of tokens.
+fn check_done_reason(done_reason: Option<&str>) -> Result<(), LlmError> {
+    match done_reason {
+        Some("length") => Err(LlmError::Truncated(
+            "Ollama API Error: the response was truncated at the num_predict limit".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Splits a newline-delimited JSON body into its lines.
+fn json_lines<S, B, E>(bytes: S) -> impl Stream<Item = Result<String>> + Send
+where
+    S: Stream<Item = std::result::Result<B, E>> + Unpin + Send,
+    B: AsRef<[u8]>,
+    E: Into<anyhow::Error>,
+{
+    stream::unfold(
+        (bytes, Vec::new(), false),
+        |(mut inner, mut buffer, mut done)| async move {
+            loop {
+                if let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    let line = String::from_utf8_lossy(&line).trim().to_string();
+                    if !line.is_empty() {
+                        return Some((Ok(line), (inner, buffer, done)));
+                    }
+                    continue;
+                }
+                if done {
+                    return None;
+                }
+                match inner.next().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(chunk.as_ref()),
+                    Some(Err(e)) => return Some((Err(e.into()), (inner, buffer, true))),
+                    None => {
+                        done = true;
+                        buffer.push(b'\n');
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Extracts the text carried by a single streamed chat chunk.
+fn stream_delta(line: String) -> Result<Option<String>> {
+    if let Some(error) = API.api_error(StatusCode::OK, &line) {
+        return Err(error.into());
+    }
+    let chunk: ChatResponse = serde_json::from_str(&line).map_err(|e| API.malformed(e))?;
+    check_done_reason(chunk.done_reason.as_deref())?;
+    Ok(chunk
+        .message
+        .map(|message| message.content)
+        .filter(|text| !text.is_empty()))
+}
+
+#[async_trait::async_trait]
+impl LlmBackend for OllamaBackend {
+    async fn request(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let response = retry::send(&self.retry, || self.post(request, false)).await?;
+        let chat_response: ChatResponse = API.read_json(response).await?;
+        check_done_reason(chat_response.done_reason.as_deref())?;
+        let usage = match (chat_response.prompt_eval_count, chat_response.eval_count) {
+            (None, None) => None,
+            (input, output) => Some(Usage {
+                input_tokens: input.unwrap_or_default(),
+                output_tokens: output.unwrap_or_default(),
+            }),
+        };
+
+        Ok(LlmResponse {
+            text: chat_response
+                .message
+                .map(|message| message.content)
+                .unwrap_or_default(),
+            stop_reason: chat_response
+                .done_reason
+                .or_else(|| chat_response.done.then(|| "stop".to_string())),
+            usage,
+        })
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let full_url = format!("{}/api/tags", self.host);
+        let response = self
+            .client
+            .get(&full_url)
+            .send()
+            .await
+            .map_err(LlmError::from)?;
+        let tags_response: TagsResponse = API.read_json(response).await?;
+        Ok(tags_response.models.into_iter().map(|m| m.name).collect())
+    }
+
+    async fn stream(&self, request: &LlmRequest) -> Result<TextStream> {
+        let response = retry::send(&self.retry, || self.post(request, true)).await?;
+        let response = API.check_status(response).await?;
+
+        let deltas = json_lines(response.bytes_stream().map_err(LlmError::from))
+            .try_filter_map(|line| async move { stream_delta(line) });
+        Ok(Box::pin(deltas))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    fn test_request() -> LlmRequest {
+        LlmRequest {
+            system_prompt: Some("Be direct.".to_string()),
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ollama_backend_request() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/chat")
+            .match_body(Matcher::Json(serde_json::json!({
+                "model": "llama3.2",
+                "messages": [
+                    {"role": "system", "content": "Be direct."},
+                    {"role": "user", "content": "test prompt"},
+                ],
+                "stream": false,
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"model":"llama3.2","created_at":"2024-07-22T20:33:28Z","message":{"role":"assistant","content":"Mocked Ollama response"},"done":true,"done_reason":"stop","prompt_eval_count":26,"eval_count":290}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = OllamaBackend::new(server.url(), "llama3.2".to_string());
+        let response = backend.request(&test_request()).await.unwrap();
+        assert_eq!(response.text, "Mocked Ollama response");
+        assert_eq!(response.stop_reason.as_deref(), Some("stop"));
+        assert_eq!(
+            response.usage,
+            Some(Usage {
+                input_tokens: 26,
+                output_tokens: 290
+            })
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_ollama_backend_request_json_schema() {
+        let mut server = Server::new_async().await;
+        let schema = serde_json::json!({"type": "object", "required": ["title"]});
+        let mock = server
+            .mock("POST", "/api/chat")
+            .match_body(Matcher::PartialJson(serde_json::json!({"format": schema})))
+            .with_status(200)
+            .with_body(
+                r#"{"message":{"role":"assistant","content":"{\"title\":\"x\"}"},"done":true,"done_reason":"stop"}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = OllamaBackend::new(server.url(), "llama3.2".to_string());
+        let request = LlmRequest {
+            json_schema: Some(schema),
+            ..test_request()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, r#"{"title":"x"}"#);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_ollama_backend_request_error() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/api/chat")
+            .with_status(404)
+            .with_body(r#"{"error":"model \"mistral\" not found, try pulling it first"}"#)
+            .create_async()
+            .await;
+
+        let backend = OllamaBackend::new(server.url(), "mistral".to_string());
+        let err = backend.request(&test_request()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Ollama API Error: model \"mistral\" not found, try pulling it first"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ollama_backend_request_truncated() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/api/chat")
+            .with_status(200)
+            .with_body(
+                r#"{"message":{"role":"assistant","content":"Half an ans"},"done":true,"done_reason":"length"}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = OllamaBackend::new(server.url(), "llama3.2".to_string());
+        let err = backend.request(&test_request()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::Truncated(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_backend_list_models() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/api/tags")
+            .with_status(200)
+            .with_body(
+                r#"{"models":[{"name":"llama3.2:latest","model":"llama3.2:latest","size":2019393189},{"name":"qwen2.5-coder:7b","model":"qwen2.5-coder:7b","size":4683087332}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = OllamaBackend::new(format!("{}/", server.url()), "llama3.2".to_string());
+        let models = backend.list_models().await.unwrap();
+        assert_eq!(models, vec!["llama3.2:latest", "qwen2.5-coder:7b"]);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_ollama_backend_stream() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/chat")
+            .match_body(Matcher::PartialJson(serde_json::json!({"stream": true})))
+            .with_status(200)
+            .with_header("content-type", "application/x-ndjson")
+            .with_body(concat!(
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"Hello\"},\"done\":false}\n",
+                "{\"message\":{\"role\":\"assistant\",\"content\":\", world\"},\"done\":false}\n",
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"done_reason\":\"stop\"}",
+            ))
+            .create_async()
+            .await;
+
+        let backend = OllamaBackend::new(server.url(), "llama3.2".to_string());
+        let deltas: Vec<String> = backend
+            .stream(&test_request())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(deltas, vec!["Hello", ", world"]);
+        mock.assert_async().await;
+    }
+}
@@ -0,0 +1,493 @@
+use super::error::{LlmError, ProviderApi};
+use super::retry::{self, RetryPolicy};
+use super::sse;
+use super::r#trait::{LlmBackend, LlmRequest, LlmResponse, TextStream, Usage};
+use anyhow::Result;
+use futures_util::TryStreamExt;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// A backend for any server speaking the OpenAI chat completions protocol
+/// (OpenAI itself, vLLM, LM Studio, llama.cpp server, ...).
+pub struct OpenAiBackend {
+    api_key: Option<String>,
+    client: Client,
+    base_url: String,
+    model: String,
+    retry: RetryPolicy,
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Serialize)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChoiceMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: ChunkDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<Model>,
+}
+
+#[derive(Deserialize)]
+struct Model {
+    id: String,
+}
+
+impl OpenAiBackend {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            api_key: api_key.filter(|key| !key.is_empty()),
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn authorize(&self, builder: RequestBuilder) -> RequestBuilder {
+        let builder = builder.header("User-Agent", "inkspect/0.1.0");
+        match &self.api_key {
+            Some(api_key) => builder.bearer_auth(api_key),
+            None => builder,
+        }
+    }
+
+    fn post(&self, request: &LlmRequest, stream: bool) -> RequestBuilder {
+        let full_url = format!("{}/chat/completions", self.base_url);
+
+        let mut messages = Vec::new();
+        if let Some(system_prompt) = &request.system_prompt {
+            messages.push(Message {
+                role: "system".to_string(),
+                content: system_prompt.clone(),
+            });
+        }
+        messages.extend(request.messages().into_iter().map(|message| Message {
+            role: message.role.as_str().to_string(),
+            content: message.content,
+        }));
+
+        let params = request.params.clone();
+        let request_body = ChatRequest {
+            model: self.model.clone(),
+            messages,
+            temperature: params.temperature,
+            top_p: params.top_p,
+            max_tokens: params.max_output_tokens,
+            stop: params.stop_sequences,
+            seed: params.seed,
+            response_format: request.json_schema.as_ref().map(|schema| {
+                serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": {"name": "response", "schema": schema},
+                })
+            }),
+            stream,
+        };
+
+        self.authorize(self.client.post(&full_url))
+            .json(&request_body)
+    }
+}
+
+const API: ProviderApi = ProviderApi {
+    name: "OpenAI API",
+    classify,
+};
+
+/// Maps an error payload onto an [`LlmError`]. OpenAI nests the details in
+/// an `error` object while vLLM and friends put them at the top level.
+fn classify(status: StatusCode, json_value: &serde_json::Value) -> Option<LlmError> {
+    let (message, code) = match json_value.get("error") {
+        Some(serde_json::Value::String(message)) => (message.as_str(), ""),
+        Some(error) => (
+            error.get("message")?.as_str().unwrap_or("Unknown error"),
+            error.get("code").and_then(|c| c.as_str()).unwrap_or(""),
+        ),
+        None if json_value.get("object")?.as_str() == Some("error") => (
+            json_value
+                .get("message")?
+                .as_str()
+                .unwrap_or("Unknown error"),
+            "",
+        ),
+        None => return None,
+    };
+    let message = format!("OpenAI API Error: {}", message);
+    Some(match code {
+        "invalid_api_key" => LlmError::Auth(message),
+        "rate_limit_exceeded" | "insufficient_quota" => LlmError::RateLimited(message),
+        "context_length_exceeded" => LlmError::ContextTooLong(message),
+        _ => LlmError::from_status(status, message),
+    })
+}
+
+/// Fails on finish reasons that leave the answer unusable.
+fn check_finish_reason(finish_reason: Option<&str>) -> Result<(), LlmError> {
+    match finish_reason {
+        Some("length") => Err(LlmError::Truncated(
+            "OpenAI API Error: the response was truncated at the max tokens limit".to_string(),
+        )),
+        Some("content_filter") => Err(LlmError::ContentBlocked(
+            "OpenAI API Error: the response was blocked by the content filter".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Extracts the text carried by a single chat completion chunk.
+fn stream_delta(event: sse::SseEvent) -> Result<Option<String>> {
+    if event.data == "[DONE]" {
+        return Ok(None);
+    }
+    if let Some(error) = API.api_error(StatusCode::OK, &event.data) {
+        return Err(error.into());
+    }
+    let chunk: ChatChunk = serde_json::from_str(&event.data).map_err(|e| API.malformed(e))?;
+    let Some(choice) = chunk.choices.into_iter().next() else {
+        return Ok(None);
+    };
+    check_finish_reason(choice.finish_reason.as_deref())?;
+    Ok(choice.delta.content.filter(|text| !text.is_empty()))
+}
+
+#[async_trait::async_trait]
+impl LlmBackend for OpenAiBackend {
+    async fn request(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let response = retry::send(&self.retry, || self.post(request, false)).await?;
+        let chat_response: ChatResponse = API.read_json(response).await?;
+        let choice = chat_response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| API.malformed("no choices"))?;
+        check_finish_reason(choice.finish_reason.as_deref())?;
+
+        Ok(LlmResponse {
+            text: choice.message.content.unwrap_or_default(),
+            stop_reason: choice.finish_reason,
+            usage: chat_response.usage.map(|u| Usage {
+                input_tokens: u.prompt_tokens,
+                output_tokens: u.completion_tokens,
+            }),
+        })
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let full_url = format!("{}/models", self.base_url);
+        let response = self
+            .authorize(self.client.get(&full_url))
+            .send()
+            .await
+            .map_err(LlmError::from)?;
+        let models_response: ModelsResponse = API.read_json(response).await?;
+        Ok(models_response.data.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn stream(&self, request: &LlmRequest) -> Result<TextStream> {
+        let response = retry::send(&self.retry, || self.post(request, true)).await?;
+        let response = API.check_status(response).await?;
+
+        let deltas = sse::events(response.bytes_stream().map_err(LlmError::from))
+            .try_filter_map(|event| async move { stream_delta(event) });
+        Ok(Box::pin(deltas))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    fn test_request() -> LlmRequest {
+        LlmRequest {
+            system_prompt: Some("Be direct.".to_string()),
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend_request() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", "Bearer test_api_key")
+            .match_body(Matcher::Json(serde_json::json!({
+                "model": "local-model",
+                "messages": [
+                    {"role": "system", "content": "Be direct."},
+                    {"role": "user", "content": "test prompt"},
+                ],
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id":"chatcmpl-1","object":"chat.completion","choices":[{"index":0,"message":{"role":"assistant","content":"Mocked OpenAI response"},"finish_reason":"stop"}],"usage":{"prompt_tokens":9,"completion_tokens":3,"total_tokens":12}}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = OpenAiBackend::new(
+            format!("{}/v1/", server.url()),
+            Some("test_api_key".to_string()),
+            "local-model".to_string(),
+        );
+        let response = backend.request(&test_request()).await.unwrap();
+        assert_eq!(response.text, "Mocked OpenAI response");
+        assert_eq!(response.stop_reason.as_deref(), Some("stop"));
+        assert_eq!(
+            response.usage,
+            Some(Usage {
+                input_tokens: 9,
+                output_tokens: 3
+            })
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend_request_json_schema() {
+        let mut server = Server::new_async().await;
+        let schema = serde_json::json!({"type": "object", "required": ["title"]});
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "response_format": {
+                    "type": "json_schema",
+                    "json_schema": {"name": "response", "schema": schema},
+                },
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"content":"{\"title\":\"x\"}"},"finish_reason":"stop"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = OpenAiBackend::new(
+            format!("{}/v1/", server.url()),
+            None,
+            "local-model".to_string(),
+        );
+        let request = LlmRequest {
+            json_schema: Some(schema),
+            ..test_request()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, r#"{"title":"x"}"#);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend_request_without_api_key() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", Matcher::Missing)
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"content":"ok"},"finish_reason":"stop"}]}"#)
+            .create_async()
+            .await;
+
+        let backend = OpenAiBackend::new(
+            format!("{}/v1", server.url()),
+            Some(String::new()),
+            "local-model".to_string(),
+        );
+        let response = backend.request(&test_request()).await.unwrap();
+        assert_eq!(response.text, "ok");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend_request_error() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(404)
+            .with_body(r#"{"error":{"message":"The model `gpt-5` does not exist","type":"invalid_request_error"}}"#)
+            .create_async()
+            .await;
+
+        let backend = OpenAiBackend::new(format!("{}/v1", server.url()), None, "gpt-5".to_string());
+        let err = backend.request(&test_request()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "OpenAI API Error: The model `gpt-5` does not exist"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend_request_context_too_long() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(400)
+            .with_body(r#"{"error":{"message":"This model's maximum context length is 128000 tokens.","type":"invalid_request_error","code":"context_length_exceeded"}}"#)
+            .create_async()
+            .await;
+
+        let backend =
+            OpenAiBackend::new(format!("{}/v1", server.url()), None, "gpt-4o".to_string());
+        let err = backend.request(&test_request()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::ContextTooLong(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend_stream_truncated() {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"length\"}]}\n\n",
+                "data: [DONE]\n\n",
+            ))
+            .create_async()
+            .await;
+
+        let backend =
+            OpenAiBackend::new(format!("{}/v1", server.url()), None, "gpt-4o".to_string());
+        let result: Result<Vec<String>> = backend
+            .stream(&test_request())
+            .await
+            .unwrap()
+            .try_collect()
+            .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<LlmError>(),
+            Some(LlmError::Truncated(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend_list_models() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/v1/models")
+            .with_status(200)
+            .with_body(
+                r#"{"object":"list","data":[{"id":"llama-3-8b","object":"model"},{"id":"qwen2.5-coder","object":"model"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let backend = OpenAiBackend::new(
+            format!("{}/v1", server.url()),
+            None,
+            "llama-3-8b".to_string(),
+        );
+        let models = backend.list_models().await.unwrap();
+        assert_eq!(models, vec!["llama-3-8b", "qwen2.5-coder"]);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_backend_stream() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(Matcher::PartialJson(serde_json::json!({"stream": true})))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\", world\"}}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+                "data: [DONE]\n\n",
+            ))
+            .create_async()
+            .await;
+
+        let backend = OpenAiBackend::new(
+            format!("{}/v1", server.url()),
+            None,
+            "local-model".to_string(),
+        );
+        let deltas: Vec<String> = backend
+            .stream(&test_request())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(deltas, vec!["Hello", ", world"]);
+        mock.assert_async().await;
+    }
+}
@@ -0,0 +1,318 @@
+use super::error::LlmError;
+use anyhow::Result;
+use jiff::Timestamp;
+use jiff::fmt::rfc2822::DateTimeParser;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::{Duration, Instant};
+
+/// How often and for how long a failed request is retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled after every attempt.
+    pub base_delay: Duration,
+    /// Upper bound for a single backoff delay.
+    pub max_delay: Duration,
+    /// Overall time budget. No retry is scheduled past it.
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            deadline: Duration::from_secs(120),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Jittered exponential backoff for the given (zero-based) retry.
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        // "Equal jitter": keep half of the delay, randomise the other half.
+        let half = exponential / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+}
+
+/// Statuses worth another attempt: rate limiting, and server-side failures
+/// such as Anthropic's 529 "overloaded".
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+/// Reads a `Retry-After` header, either a number of seconds or an HTTP date.
+/// Anything else is ignored, and the backoff applies instead.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, Timestamp::now())
+}
+
+fn parse_retry_after(value: &str, now: Timestamp) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTimeParser::new().parse_timestamp(value).ok()?;
+    // A date already past means retrying right away.
+    Some(Duration::try_from(now.duration_until(date)).unwrap_or_default())
+}
+
+/// Sends the request built by `build`, retrying transient failures according
+/// to `policy`. The last response is returned as-is once the attempts or the
+/// deadline are exhausted, so callers still see the provider's error body.
+pub async fn send<F>(policy: &RetryPolicy, build: F) -> Result<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let started = Instant::now();
+    let mut attempt = 1;
+    loop {
+        let result = build().send().await;
+        let delay = match &result {
+            Ok(response) if is_transient_status(response.status()) => {
+                retry_after(response).unwrap_or_else(|| policy.backoff(attempt - 1))
+            }
+            Err(error) if is_transient_error(error) => policy.backoff(attempt - 1),
+            _ => return result.map_err(|e| LlmError::from(e).into()),
+        };
+
+        if attempt >= policy.max_attempts
+            || started.elapsed().saturating_add(delay) > policy.deadline
+        {
+            return result.map_err(|e| LlmError::from(e).into());
+        }
+
+        match &result {
+            Ok(response) => log::debug!(
+                "Attempt {}/{} failed with HTTP {}, retrying in {:?}",
+                attempt,
+                policy.max_attempts,
+                response.status(),
+                delay
+            ),
+            Err(error) => log::debug!(
+                "Attempt {}/{} failed: {}, retrying in {:?}",
+                attempt,
+                policy.max_attempts,
+                error,
+                delay
+            ),
+        }
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+    use reqwest::Client;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            deadline: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            deadline: Duration::from_secs(60),
+        };
+        let first = policy.backoff(0);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let third = policy.backoff(2);
+        assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+        let capped = policy.backoff(20);
+        assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now: Timestamp = "2025-03-01T12:00:00Z".parse().unwrap();
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sat, 01 Mar 2025 12:00:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Sat, 01 Mar 2025 11:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        for invalid in ["-1", "1.5", "inf", "NaN", "1e30", "soon", ""] {
+            assert_eq!(parse_retry_after(invalid, now), None, "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_statuses_until_success() {
+        let mut server = Server::new_async().await;
+        let rate_limited = server
+            .mock("POST", "/")
+            .with_status(429)
+            .expect(1)
+            .create_async()
+            .await;
+        let overloaded = server
+            .mock("POST", "/")
+            .with_status(529)
+            .expect(1)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/")
+            .with_status(200)
+            .with_body("done")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let url = server.url();
+        let response = send(&fast_policy(3), || client.post(&url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "done");
+        rate_limited.assert_async().await;
+        overloaded.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_returns_last_response_when_attempts_are_exhausted() {
+        let mut server = Server::new_async().await;
+        let unavailable = server
+            .mock("POST", "/")
+            .with_status(503)
+            .with_body("still down")
+            .expect(2)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let url = server.url();
+        let response = send(&fast_policy(2), || client.post(&url)).await.unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.text().await.unwrap(), "still down");
+        unavailable.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let mut server = Server::new_async().await;
+        let unauthorized = server
+            .mock("POST", "/")
+            .with_status(401)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let url = server.url();
+        let response = send(&fast_policy(5), || client.post(&url)).await.unwrap();
+        assert_eq!(response.status(), 401);
+        unauthorized.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_honours_retry_after() {
+        let mut server = Server::new_async().await;
+        let _rate_limited = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "1")
+            .expect(1)
+            .create_async()
+            .await;
+        let _ok = server
+            .mock("POST", "/")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let url = server.url();
+        let started = Instant::now();
+        let response = send(&fast_policy(2), || client.post(&url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_retry_after_falls_back_to_backoff() {
+        let mut server = Server::new_async().await;
+        let rate_limited = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "-1")
+            .expect(1)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let url = server.url();
+        let response = send(&fast_policy(2), || client.post(&url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        rate_limited.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_when_retry_after_exceeds_deadline() {
+        let mut server = Server::new_async().await;
+        let rate_limited = server
+            .mock("POST", "/")
+            .with_status(429)
+            .with_header("retry-after", "3600")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let url = server.url();
+        let response = send(&fast_policy(5), || client.post(&url)).await.unwrap();
+        assert_eq!(response.status(), 429);
+        rate_limited.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_surface_after_retries() {
+        // Nothing listens on port 1.
+        let client = Client::new();
+        let result = send(&fast_policy(3), || client.post("http://127.0.0.1:1")).await;
+        let error = result.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LlmError>(),
+            Some(LlmError::Network(_))
+        ));
+    }
+}
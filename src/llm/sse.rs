@@ -0,0 +1,132 @@
+use anyhow::Result;
+use futures_util::{Stream, StreamExt, stream};
+
+/// A single server-sent event.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+struct State<S> {
+    inner: S,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl<S> State<S> {
+    fn next_event(&mut self) -> Option<SseEvent> {
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            if let Some(event) = parse_event(&String::from_utf8_lossy(&block)) {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
+
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let mut event = SseEvent::default();
+    let mut data_lines = Vec::new();
+    for line in block.lines() {
+        if line.is_empty() || line.starts_with(':') {
+            continue;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => event.event = Some(value.to_string()),
+            "data" => data_lines.push(value),
+            _ => {}
+        }
+    }
+    if data_lines.is_empty() && event.event.is_none() {
+        return None;
+    }
+    event.data = data_lines.join("\n");
+    Some(event)
+}
+
+/// Turns a byte stream carrying a `text/event-stream` body into a stream of events.
+pub fn events<S, B, E>(bytes: S) -> impl Stream<Item = Result<SseEvent>> + Send
+where
+    S: Stream<Item = std::result::Result<B, E>> + Unpin + Send,
+    B: AsRef<[u8]>,
+    E: Into<anyhow::Error>,
+{
+    let state = State {
+        inner: bytes,
+        buffer: Vec::new(),
+        done: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.next_event() {
+                return Some((Ok(event), state));
+            }
+            if state.done {
+                return None;
+            }
+            match state.inner.next().await {
+                Some(Ok(chunk)) => state
+                    .buffer
+                    .extend(chunk.as_ref().iter().copied().filter(|b| *b != b'\r')),
+                Some(Err(e)) => {
+                    state.done = true;
+                    return Some((Err(e.into()), state));
+                }
+                None => {
+                    state.done = true;
+                    state.buffer.extend_from_slice(b"\n\n");
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn collect(chunks: Vec<&'static str>) -> Vec<SseEvent> {
+        let bytes = stream::iter(chunks.into_iter().map(Ok::<_, anyhow::Error>));
+        events(bytes)
+            .map(|event| event.unwrap())
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_events_split_across_chunks() {
+        let events = collect(vec![
+            "event: delta\nda",
+            "ta: {\"a\":1}\n\n: keep-alive\n\n",
+            "data: second\r\n\r\ndata: last",
+        ])
+        .await;
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("delta".to_string()),
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "second".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "last".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_events_multiline_data() {
+        let events = collect(vec!["data: one\ndata: two\n\n"]).await;
+        assert_eq!(events[0].data, "one\ntwo");
+    }
+}
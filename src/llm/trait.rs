@@ -1,9 +1,126 @@
 use anyhow::Result;
+use futures_util::stream::{self, BoxStream};
+use serde::{Deserialize, Serialize};
+
+/// Sampling settings for a request. Unset fields are left to the provider's
+/// defaults; backends ignore the ones their API does not support.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GenerationParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl GenerationParams {
+    /// Returns these params with every field set in `overrides` replaced.
+    pub fn overridden_by(&self, overrides: &GenerationParams) -> Self {
+        Self {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_output_tokens: overrides.max_output_tokens.or(self.max_output_tokens),
+            stop_sequences: overrides
+                .stop_sequences
+                .clone()
+                .or_else(|| self.stop_sequences.clone()),
+            seed: overrides.seed.or(self.seed),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Who a turn of a conversation comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+impl Role {
+    /// The role name of the Claude, OpenAI and Ollama chat APIs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+/// A turn of a conversation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+}
+
+/// A single request sent to an LLM backend.
+#[derive(Debug, Clone, Default)]
+pub struct LlmRequest {
+    /// Instructions sent through the provider's dedicated system channel.
+    pub system_prompt: Option<String>,
+    /// Earlier turns of the conversation, oldest first, alternating between
+    /// user and assistant. Empty for one-shot requests.
+    pub history: Vec<Message>,
+    /// The user turn: style text followed by the user's input.
+    pub prompt: String,
+    pub params: GenerationParams,
+    /// JSON Schema the response must follow. Backends with native structured
+    /// output constrain generation to it; the caller validates the result.
+    pub json_schema: Option<serde_json::Value>,
+}
+
+impl LlmRequest {
+    /// The conversation to send: the history, then the prompt as the last
+    /// user turn.
+    pub fn messages(&self) -> Vec<Message> {
+        let mut messages = self.history.clone();
+        messages.push(Message {
+            role: Role::User,
+            content: self.prompt.clone(),
+        });
+        messages
+    }
+}
+
+/// Token accounting reported by the provider.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// The text produced by a backend along with the metadata the provider returned.
+#[derive(Debug, Clone, Default)]
+pub struct LlmResponse {
+    pub text: String,
+    pub stop_reason: Option<String>,
+    pub usage: Option<Usage>,
+}
+
+/// A stream of text deltas, in the order the provider produced them.
+pub type TextStream = BoxStream<'static, Result<String>>;
 
 #[async_trait::async_trait]
-pub trait LlmBackend {
-    async fn request(&self, full_prompt: &str) -> Result<String>;
+pub trait LlmBackend: Send + Sync {
+    async fn request(&self, request: &LlmRequest) -> Result<LlmResponse>;
     async fn list_models(&self) -> Result<Vec<String>>;
+
+    /// Streams the response as it is generated. Backends without native
+    /// streaming fall back to a single delta holding the whole response.
+    async fn stream(&self, request: &LlmRequest) -> Result<TextStream> {
+        let response = self.request(request).await?;
+        Ok(Box::pin(stream::once(async move { Ok(response.text) })))
+    }
 }
 
 #[cfg(test)]
@@ -14,18 +131,72 @@ mod tests {
 
     #[async_trait::async_trait]
     impl LlmBackend for MockLlmBackend {
-        async fn request(&self, full_prompt: &str) -> Result<String> {
-            Ok(format!("Mocked response for prompt: '{}'", full_prompt))
+        async fn request(&self, request: &LlmRequest) -> Result<LlmResponse> {
+            Ok(LlmResponse {
+                text: format!("Mocked response for prompt: '{}'", request.prompt),
+                ..Default::default()
+            })
         }
         async fn list_models(&self) -> Result<Vec<String>> {
             Ok(vec!["model1".to_string(), "model2".to_string()])
         }
     }
 
+    #[test]
+    fn test_generation_params_overridden_by() {
+        let provider = GenerationParams {
+            temperature: Some(0.7),
+            max_output_tokens: Some(1024),
+            stop_sequences: Some(vec!["END".to_string()]),
+            ..Default::default()
+        };
+        let style = GenerationParams {
+            temperature: Some(0.2),
+            seed: Some(42),
+            ..Default::default()
+        };
+        let merged = provider.overridden_by(&style);
+        assert_eq!(
+            merged,
+            GenerationParams {
+                temperature: Some(0.2),
+                top_p: None,
+                max_output_tokens: Some(1024),
+                stop_sequences: Some(vec!["END".to_string()]),
+                seed: Some(42),
+            }
+        );
+        assert!(GenerationParams::default().is_empty());
+        assert!(!merged.is_empty());
+    }
+
     #[tokio::test]
     async fn test_mock_llm_backend() {
         let backend = MockLlmBackend;
-        let response = backend.request("test prompt").await.unwrap();
-        assert_eq!(response, "Mocked response for prompt: 'test prompt'");
+        let request = LlmRequest {
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        };
+        let response = backend.request(&request).await.unwrap();
+        assert_eq!(response.text, "Mocked response for prompt: 'test prompt'");
+    }
+
+    #[tokio::test]
+    async fn test_default_stream_yields_whole_response() {
+        use futures_util::TryStreamExt;
+
+        let backend = MockLlmBackend;
+        let request = LlmRequest {
+            prompt: "test prompt".to_string(),
+            ..Default::default()
+        };
+        let deltas: Vec<String> = backend
+            .stream(&request)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(deltas, vec!["Mocked response for prompt: 'test prompt'"]);
     }
 }
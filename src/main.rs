@@ -1,23 +1,41 @@
+mod backup;
+mod batch;
+mod chat;
+mod chunk;
 mod cli;
 mod config;
+mod context;
 mod core;
+mod diff;
 mod editor;
+mod extract;
 mod llm;
+mod postprocess;
+mod schema;
 mod setup;
+mod template;
 
 use anyhow::Result;
 use clap::Parser;
 use cli::{Cli, Commands};
 use config::Config;
-use llm::claude::ClaudeBackend;
-use llm::gemini::GeminiBackend;
-use llm::r#trait::LlmBackend;
+use llm::factory::create_backend;
 use log::LevelFilter;
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(llm::error::exit_code(&err))
+        }
+    }
+}
 
+async fn run(cli: Cli) -> Result<()> {
     if let Commands::Setup { config } = cli.command {
         return setup::setup(config);
     }
@@ -30,7 +48,7 @@ async fn main() -> Result<()> {
         })
         .init();
 
-    let (config, config_path): (Config, String) = if let Some(config_path) = &cli.config {
+    let (mut config, config_path): (Config, String) = if let Some(config_path) = &cli.config {
         (confy::load_path(config_path)?, config_path.clone())
     } else {
         let path = confy::get_configuration_file_path("inkspect", None)?;
@@ -40,6 +58,11 @@ async fn main() -> Result<()> {
         )
     };
 
+    if config.backups.dir.is_none() {
+        config.backups.dir = backup::default_dir();
+    }
+    config.editor.history_file = editor::default_history_file();
+
     if cli.verbose {
         log::debug!("Loaded config from: {}", config_path);
         let config_to_log = if cli.show_secrets {
@@ -50,52 +73,5 @@ async fn main() -> Result<()> {
         log::debug!("Loaded config: {:?}", config_to_log);
     }
 
-    let llm_backend: Box<dyn LlmBackend> = match &cli.command {
-        Commands::Optimize { provider, .. } => {
-            let provider_name = provider.as_deref().unwrap_or(&config.llm.provider);
-            match provider_name {
-                "gemini" => Box::new(GeminiBackend::new(
-                    config.providers.gemini.api_key.clone(),
-                    config.providers.gemini.model.clone(),
-                )),
-                "claude" => Box::new(ClaudeBackend::new(
-                    config.providers.claude.api_key.clone(),
-                    config.providers.claude.model.clone(),
-                )),
-                _ => return Err(anyhow::anyhow!("Unsupported provider")),
-            }
-        }
-        Commands::ListModels { provider } => {
-            let provider_name = provider.as_deref().unwrap_or(&config.llm.provider);
-            match provider_name {
-                "gemini" => Box::new(GeminiBackend::new(
-                    config.providers.gemini.api_key.clone(),
-                    config.providers.gemini.model.clone(),
-                )),
-                "claude" => Box::new(ClaudeBackend::new(
-                    config.providers.claude.api_key.clone(),
-                    config.providers.claude.model.clone(),
-                )),
-                _ => return Err(anyhow::anyhow!("Unsupported provider")),
-            }
-        }
-        Commands::ListPrompts => {
-            // The ListPrompts command does not require a provider.
-            let provider_name = &config.llm.provider;
-            match provider_name.as_str() {
-                "gemini" => Box::new(GeminiBackend::new(
-                    config.providers.gemini.api_key.clone(),
-                    config.providers.gemini.model.clone(),
-                )),
-                "claude" => Box::new(ClaudeBackend::new(
-                    config.providers.claude.api_key.clone(),
-                    config.providers.claude.model.clone(),
-                )),
-                _ => return Err(anyhow::anyhow!("Unsupported provider")),
-            }
-        }
-        Commands::Setup { .. } => unreachable!(), // This is handled above
-    };
-
-    core::run(cli, config, llm_backend).await
+    core::run(cli, config, &create_backend).await
 }
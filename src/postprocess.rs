@@ -0,0 +1,420 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// A transformation applied to the model's response before it is written out.
+/// Processors run in the order they are configured.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Processor {
+    /// Drops leading and trailing lines matching any of the regexes, such as
+    /// "Of course." openers or "Let me know if..." closers.
+    StripChatter {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        leading: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        trailing: Vec<String>,
+    },
+    /// Removes a Markdown code fence wrapping the whole response.
+    UnwrapFence,
+    /// Keeps only the fenced code blocks, optionally of a single language.
+    ExtractCode {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+        /// Keep every matching block instead of the first one.
+        #[serde(default)]
+        all: bool,
+    },
+    /// Removes leading and trailing whitespace.
+    Trim,
+    /// Converts `\r\n` and `\r` line endings to `\n`.
+    NormalizeLineEndings,
+}
+
+/// The processors used when neither the configuration nor the style sets any:
+/// drop the conversational openers models like to start with.
+pub fn default_processors() -> Vec<Processor> {
+    vec![Processor::StripChatter {
+        leading: vec![
+            r"Of course\.".to_string(),
+            r"Certainly\.".to_string(),
+            r"Here(?: is|'s) a refined and comprehensive explanation".to_string(),
+            r"Here(?: is|'s) a refined version".to_string(),
+        ],
+        trailing: vec![],
+    }]
+}
+
+/// A fenced code block found in a Markdown document.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CodeBlock {
+    /// Everything after the opening fence, such as `rust src/main.rs`.
+    pub(crate) info: String,
+    pub(crate) language: String,
+    pub(crate) content: String,
+    /// Line range of the block, fences included.
+    first_line: usize,
+    last_line: usize,
+}
+
+/// Finds the fenced code blocks of `text`. An unclosed fence runs to the end
+/// of the text, as in CommonMark.
+pub(crate) fn code_blocks(text: &str) -> Vec<CodeBlock> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut blocks = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index].trim_start();
+        let fence_char = match line.chars().next() {
+            Some(c @ ('`' | '~')) => c,
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        let fence_len = line.chars().take_while(|c| *c == fence_char).count();
+        if fence_len < 3 {
+            index += 1;
+            continue;
+        }
+        let info = line[fence_len..].trim().to_string();
+        let language = info
+            .split(|c: char| c.is_whitespace() || c == ':')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        let first_line = index;
+        let mut content = String::new();
+        index += 1;
+        while index < lines.len() {
+            let inner = lines[index].trim();
+            let closing_len = inner.chars().take_while(|c| *c == fence_char).count();
+            if closing_len >= fence_len && closing_len == inner.chars().count() {
+                break;
+            }
+            content.push_str(lines[index]);
+            content.push('\n');
+            index += 1;
+        }
+        blocks.push(CodeBlock {
+            info,
+            language,
+            content,
+            first_line,
+            last_line: index.min(lines.len() - 1),
+        });
+        index += 1;
+    }
+    blocks
+}
+
+fn strip_chatter(text: &str, leading: &[Regex], trailing: &[Regex]) -> String {
+    let is_chatter = |line: &str, patterns: &[Regex]| patterns.iter().any(|p| p.is_match(line));
+    let lines: Vec<&str> = text.lines().collect();
+
+    let mut start = 0;
+    while start < lines.len() && is_chatter(lines[start], leading) {
+        start += 1;
+        while start < lines.len() && lines[start].trim().is_empty() {
+            start += 1;
+        }
+    }
+    let mut end = lines.len();
+    while end > start && is_chatter(lines[end - 1], trailing) {
+        end -= 1;
+        while end > start && lines[end - 1].trim().is_empty() {
+            end -= 1;
+        }
+    }
+
+    if start == 0 && end == lines.len() {
+        return text.to_string();
+    }
+    let mut stripped = lines[start..end].join("\n");
+    if end == lines.len() && text.ends_with('\n') {
+        stripped.push('\n');
+    }
+    stripped
+}
+
+fn unwrap_fence(text: &str) -> String {
+    let trimmed = text.trim();
+    let blocks = code_blocks(trimmed);
+    match blocks.as_slice() {
+        [block] if block.first_line == 0 && block.last_line + 1 == trimmed.lines().count() => {
+            block.content.clone()
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn extract_code(text: &str, language: Option<&str>, all: bool) -> String {
+    let mut blocks = code_blocks(text)
+        .into_iter()
+        .filter(|block| language.is_none_or(|l| block.language.eq_ignore_ascii_case(l)));
+    let extracted = if all {
+        blocks
+            .map(|block| block.content)
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        blocks.next().map(|block| block.content).unwrap_or_default()
+    };
+    if extracted.is_empty() {
+        log::warn!(
+            "No {}code block found in the response, keeping it as is",
+            language.map(|l| format!("{} ", l)).unwrap_or_default()
+        );
+        return text.to_string();
+    }
+    extracted
+}
+
+fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// A processor with its regexes compiled.
+enum Compiled {
+    StripChatter {
+        leading: Vec<Regex>,
+        trailing: Vec<Regex>,
+    },
+    UnwrapFence,
+    ExtractCode {
+        language: Option<String>,
+        all: bool,
+    },
+    Trim,
+    NormalizeLineEndings,
+}
+
+/// A chain of processors, validated once before any request is sent.
+pub struct PostProcessor {
+    processors: Vec<Compiled>,
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern)
+                .map_err(|e| anyhow::anyhow!("Invalid post-processing regex '{}': {}", pattern, e))
+        })
+        .collect()
+}
+
+impl PostProcessor {
+    pub fn new(processors: &[Processor]) -> Result<Self> {
+        let processors = processors
+            .iter()
+            .map(|processor| {
+                Ok(match processor {
+                    Processor::StripChatter { leading, trailing } => Compiled::StripChatter {
+                        leading: compile(leading)?,
+                        trailing: compile(trailing)?,
+                    },
+                    Processor::UnwrapFence => Compiled::UnwrapFence,
+                    Processor::ExtractCode { language, all } => Compiled::ExtractCode {
+                        language: language.clone(),
+                        all: *all,
+                    },
+                    Processor::Trim => Compiled::Trim,
+                    Processor::NormalizeLineEndings => Compiled::NormalizeLineEndings,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { processors })
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        self.processors
+            .iter()
+            .fold(text.to_string(), |text, processor| match processor {
+                Compiled::StripChatter { leading, trailing } => {
+                    strip_chatter(&text, leading, trailing)
+                }
+                Compiled::UnwrapFence => unwrap_fence(&text),
+                Compiled::ExtractCode { language, all } => {
+                    extract_code(&text, language.as_deref(), *all)
+                }
+                Compiled::Trim => text.trim().to_string(),
+                Compiled::NormalizeLineEndings => normalize_line_endings(&text),
+            })
+    }
+
+    /// Whether the output can be printed as it streams in: only leading
+    /// chatter can be held back, so every other processor but whitespace
+    /// clean-up needs the full response.
+    pub fn is_streamable(&self) -> bool {
+        self.processors.iter().all(|processor| match processor {
+            Compiled::StripChatter { trailing, .. } => trailing.is_empty(),
+            Compiled::Trim | Compiled::NormalizeLineEndings => true,
+            Compiled::UnwrapFence | Compiled::ExtractCode { .. } => false,
+        })
+    }
+
+    /// Whether a streamed first line should be held back as chatter.
+    pub fn is_leading_chatter(&self, line: &str) -> bool {
+        self.processors.iter().any(|processor| match processor {
+            Compiled::StripChatter { leading, .. } => leading.iter().any(|p| p.is_match(line)),
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(processor: Processor, text: &str) -> String {
+        PostProcessor::new(&[processor]).unwrap().apply(text)
+    }
+
+    #[test]
+    fn test_strip_chatter_default() {
+        let processor = PostProcessor::new(&default_processors()).unwrap();
+        assert_eq!(
+            processor.apply("Of course. Here it is:\nThe answer\nMore"),
+            "The answer\nMore"
+        );
+        assert_eq!(processor.apply("The answer\n"), "The answer\n");
+        assert!(processor.is_leading_chatter("Certainly. Here you go"));
+        assert!(!processor.is_leading_chatter("The answer"));
+    }
+
+    #[test]
+    fn test_strip_chatter_leading_and_trailing() {
+        let processor = Processor::StripChatter {
+            leading: vec![r"^Sure[,!]".to_string(), r"^Here is".to_string()],
+            trailing: vec![r"(?i)^let me know".to_string()],
+        };
+        assert_eq!(
+            apply(
+                processor,
+                "Sure! Happy to help.\n\nHere is the result:\nLine 1\nLine 2\n\nLet me know if you need more.\n"
+            ),
+            "Line 1\nLine 2"
+        );
+    }
+
+    #[test]
+    fn test_strip_chatter_invalid_regex() {
+        let err = PostProcessor::new(&[Processor::StripChatter {
+            leading: vec!["(unclosed".to_string()],
+            trailing: vec![],
+        }])
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(err.starts_with("Invalid post-processing regex '(unclosed'"));
+    }
+
+    #[test]
+    fn test_unwrap_fence() {
+        assert_eq!(
+            apply(
+                Processor::UnwrapFence,
+                "```markdown\n# Title\n\nBody\n```\n"
+            ),
+            "# Title\n\nBody\n"
+        );
+        assert_eq!(
+            apply(Processor::UnwrapFence, "  ~~~~\ncode ``` inside\n~~~~  "),
+            "code ``` inside\n"
+        );
+        // Text around the fence, or several fences, are left alone.
+        let mixed = "Intro\n```rust\nfn main() {}\n```\n";
+        assert_eq!(apply(Processor::UnwrapFence, mixed), mixed);
+        let two = "```\na\n```\n```\nb\n```";
+        assert_eq!(apply(Processor::UnwrapFence, two), two);
+    }
+
+    #[test]
+    fn test_extract_code() {
+        let text = "Intro\n```python\nprint(1)\n```\nThen\n```rust\nfn a() {}\n```\n```Rust\nfn b() {}\n```\n";
+        let first_rust = Processor::ExtractCode {
+            language: Some("rust".to_string()),
+            all: false,
+        };
+        assert_eq!(apply(first_rust, text), "fn a() {}\n");
+        let all_rust = Processor::ExtractCode {
+            language: Some("rust".to_string()),
+            all: true,
+        };
+        assert_eq!(apply(all_rust, text), "fn a() {}\n\nfn b() {}\n");
+        let first_any = Processor::ExtractCode {
+            language: None,
+            all: false,
+        };
+        assert_eq!(apply(first_any, text), "print(1)\n");
+        let missing = Processor::ExtractCode {
+            language: Some("go".to_string()),
+            all: false,
+        };
+        assert_eq!(apply(missing, text), text);
+    }
+
+    #[test]
+    fn test_trim() {
+        assert_eq!(apply(Processor::Trim, "\n\n  answer \n\n"), "answer");
+    }
+
+    #[test]
+    fn test_normalize_line_endings() {
+        assert_eq!(
+            apply(Processor::NormalizeLineEndings, "a\r\nb\rc\n"),
+            "a\nb\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_processors_run_in_order() {
+        let processor = PostProcessor::new(&[
+            Processor::NormalizeLineEndings,
+            Processor::UnwrapFence,
+            Processor::Trim,
+        ])
+        .unwrap();
+        assert_eq!(processor.apply("```\r\n  body  \r\n```\r\n"), "body");
+        assert!(!processor.is_streamable());
+        assert!(
+            PostProcessor::new(&default_processors())
+                .unwrap()
+                .is_streamable()
+        );
+    }
+
+    #[test]
+    fn test_processors_from_toml() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            postprocess: Vec<Processor>,
+        }
+        let wrapper: Wrapper = toml::from_str(
+            r#"
+postprocess = [
+    { kind = "strip_chatter", leading = ["^Sure"] },
+    { kind = "extract_code", language = "rust", all = true },
+    { kind = "trim" },
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            wrapper.postprocess,
+            vec![
+                Processor::StripChatter {
+                    leading: vec!["^Sure".to_string()],
+                    trailing: vec![],
+                },
+                Processor::ExtractCode {
+                    language: Some("rust".to_string()),
+                    all: true,
+                },
+                Processor::Trim,
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::postprocess;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// How many times a response that does not match the schema is sent back to
+/// the model before giving up, unless the style sets `schema_retries`.
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// A style's JSON Schema: a path to a JSON file, or the schema itself written
+/// inline in the configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SchemaSource {
+    Path(PathBuf),
+    Inline(Value),
+}
+
+/// A compiled JSON Schema that responses are validated against.
+pub struct JsonSchema {
+    value: Value,
+    validator: jsonschema::Validator,
+}
+
+impl JsonSchema {
+    pub fn load(source: &SchemaSource) -> Result<Self> {
+        let value = match source {
+            SchemaSource::Inline(value) => value.clone(),
+            SchemaSource::Path(path) => {
+                let content = std::fs::read_to_string(path).map_err(|e| {
+                    anyhow::anyhow!("Failed to read JSON Schema '{}': {}", path.display(), e)
+                })?;
+                serde_json::from_str(&content).map_err(|e| {
+                    anyhow::anyhow!("Failed to parse JSON Schema '{}': {}", path.display(), e)
+                })?
+            }
+        };
+        let validator = jsonschema::validator_for(&value)
+            .map_err(|e| anyhow::anyhow!("Invalid JSON Schema: {}", e))?;
+        Ok(Self { value, validator })
+    }
+
+    /// The schema as sent to providers with native structured output.
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Parses `response` as JSON and validates it, returning the document or
+    /// every problem found. A response wrapped in a code fence is accepted.
+    pub fn validate(&self, response: &str) -> Result<Value, Vec<String>> {
+        let document: Value = match serde_json::from_str(response.trim()) {
+            Ok(document) => document,
+            Err(e) => postprocess::code_blocks(response)
+                .first()
+                .and_then(|block| serde_json::from_str(&block.content).ok())
+                .ok_or_else(|| vec![format!("the response is not valid JSON: {}", e)])?,
+        };
+        let errors: Vec<String> = self
+            .validator
+            .iter_errors(&document)
+            .map(|error| {
+                let path = error.instance_path.to_string();
+                if path.is_empty() {
+                    error.to_string()
+                } else {
+                    format!("at {}: {}", path, error)
+                }
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(document)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The prompt for another attempt after `response` failed validation.
+pub fn retry_prompt(prompt: &str, response: &str, errors: &[String]) -> String {
+    format!(
+        "{}\n\nYour previous response was:\n{}\n\nIt does not match the required JSON Schema:\n- {}\n\nRespond again with only the corrected JSON document.",
+        prompt,
+        response.trim(),
+        errors.join("\n- ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> JsonSchema {
+        JsonSchema::load(&SchemaSource::Inline(json!({
+            "type": "object",
+            "properties": {
+                "title": {"type": "string"},
+                "priority": {"type": "integer", "minimum": 1}
+            },
+            "required": ["title", "priority"]
+        })))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_accepts_matching_json() {
+        let document = schema()
+            .validate("{\"title\": \"Fix login\", \"priority\": 2}\n")
+            .unwrap();
+        assert_eq!(document, json!({"title": "Fix login", "priority": 2}));
+
+        let fenced = "```json\n{\"title\": \"Fix login\", \"priority\": 2}\n```";
+        assert_eq!(schema().validate(fenced).unwrap(), document);
+    }
+
+    #[test]
+    fn test_validate_reports_every_error() {
+        let errors = schema()
+            .validate("{\"title\": 3, \"priority\": 0}")
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|e| e.starts_with("at /title:")));
+        assert!(errors.iter().any(|e| e.starts_with("at /priority:")));
+
+        let errors = schema().validate("Sure! Here it is.").unwrap_err();
+        assert!(errors[0].starts_with("the response is not valid JSON"));
+    }
+
+    #[test]
+    fn test_load_from_path() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, br#"{"type": "array"}"#).unwrap();
+        let schema = JsonSchema::load(&SchemaSource::Path(file.path().to_path_buf())).unwrap();
+        assert_eq!(schema.value(), &json!({"type": "array"}));
+        assert!(schema.validate("[1, 2]").is_ok());
+
+        let err = JsonSchema::load(&SchemaSource::Path("/nonexistent/schema.json".into()))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.starts_with("Failed to read JSON Schema '/nonexistent/schema.json'"));
+        let err = JsonSchema::load(&SchemaSource::Inline(json!({"type": 12})))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.starts_with("Invalid JSON Schema"));
+    }
+
+    #[test]
+    fn test_retry_prompt() {
+        let prompt = retry_prompt("Summarize.", "{}\n", &["a".to_string(), "b".to_string()]);
+        assert_eq!(
+            prompt,
+            "Summarize.\n\nYour previous response was:\n{}\n\nIt does not match the required JSON Schema:\n- a\n- b\n\nRespond again with only the corrected JSON document."
+        );
+    }
+}
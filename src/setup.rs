@@ -37,16 +37,20 @@ pub fn setup(config_path_opt: Option<String>) -> Result<()> {
     io::stdout().flush()?;
     let mut gemini_key = String::new();
     io::stdin().read_line(&mut gemini_key)?;
-    if !gemini_key.trim().is_empty() {
-        config.providers.gemini.api_key = gemini_key.trim().to_string();
+    if !gemini_key.trim().is_empty()
+        && let Some(gemini) = config.providers.get_mut("gemini")
+    {
+        gemini.api_key = gemini_key.trim().to_string();
     }
 
     print!("Enter your Claude API Key (leave blank to skip): ");
     io::stdout().flush()?;
     let mut claude_key = String::new();
     io::stdin().read_line(&mut claude_key)?;
-    if !claude_key.trim().is_empty() {
-        config.providers.claude.api_key = claude_key.trim().to_string();
+    if !claude_key.trim().is_empty()
+        && let Some(claude) = config.providers.get_mut("claude")
+    {
+        claude.api_key = claude_key.trim().to_string();
     }
 
     confy::store_path(&config_path, config)?;
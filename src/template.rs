@@ -0,0 +1,157 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// The placeholder that receives the user's input.
+pub const INPUT: &str = "input";
+
+/// Whether `name` can be used as a variable: letters, digits, `_` and `-`.
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Splits a template into literal text and `{{name}}` placeholders. Braces
+/// that do not enclose a valid name are kept as literal text, and so are
+/// braces escaped as `\{{name}}`, minus the backslash.
+fn segments(template: &str) -> Vec<(&str, Option<&str>)> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    let mut literal_start = 0;
+    let mut offset = 0;
+    while let Some(open) = rest.find("{{") {
+        if rest[..open].ends_with('\\') {
+            segments.push((&template[literal_start..offset + open - 1], None));
+            literal_start = offset + open;
+            offset += open + 2;
+            rest = &rest[open + 2..];
+            continue;
+        }
+        let after_open = &rest[open + 2..];
+        let Some(close) = after_open.find("}}") else {
+            break;
+        };
+        let name = after_open[..close].trim();
+        let end = open + 2 + close + 2;
+        if is_variable_name(name) {
+            segments.push((&template[literal_start..offset + open], Some(name)));
+            literal_start = offset + end;
+            offset += end;
+            rest = &rest[end..];
+        } else {
+            offset += open + 2;
+            rest = &rest[open + 2..];
+        }
+    }
+    segments.push((&template[literal_start..], None));
+    segments
+}
+
+/// The distinct variables a template refers to, in order of appearance.
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (_, name) in segments(template) {
+        if let Some(name) = name
+            && !names.iter().any(|n| n == name)
+        {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Fills every `{{name}}` placeholder in `template` from `vars`. A placeholder
+/// without a value is an error rather than being left in the prompt.
+pub fn render(template: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    for (literal, name) in segments(template) {
+        rendered.push_str(literal);
+        if let Some(name) = name {
+            let value = vars.get(name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Template variable '{{{{{}}}}}' has no value. Pass it with --var {}=<value>, or write \\{{{{{}}}}} to keep it as text",
+                    name,
+                    name,
+                    name
+                )
+            })?;
+            rendered.push_str(value);
+        }
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_fills_placeholders() {
+        let rendered = render(
+            "Review this {{language}} file ({{ filename }}):\n{{input}}\nFocus on {{language}}.",
+            &vars(&[
+                ("input", "fn main() {}"),
+                ("language", "Rust"),
+                ("filename", "main.rs"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            rendered,
+            "Review this Rust file (main.rs):\nfn main() {}\nFocus on Rust."
+        );
+    }
+
+    #[test]
+    fn test_render_unfilled_variable_is_an_error() {
+        let err = render(
+            "Translate to {{language}}: {{input}}",
+            &vars(&[("input", "x")]),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(
+            err,
+            "Template variable '{{language}}' has no value. Pass it with --var language=<value>, or write \\{{language}} to keep it as text"
+        );
+    }
+
+    #[test]
+    fn test_escaped_braces_are_literal() {
+        let template = "Use \\{{user}} in the view, and {{ name }} as \\{{ name }}: {{input}}";
+        assert_eq!(placeholders(template), vec!["name", "input"]);
+        assert_eq!(
+            render(template, &vars(&[("input", "here"), ("name", "Ada")])).unwrap(),
+            "Use {{user}} in the view, and Ada as {{ name }}: here"
+        );
+        assert!(placeholders("Go templates: \\{{.Name}} and \\{{user}}").is_empty());
+    }
+
+    #[test]
+    fn test_braces_that_are_not_placeholders_are_kept() {
+        let template = "Use {{ }} or {{not a var}} and {single} braces {{input}}";
+        assert_eq!(placeholders(template), vec!["input"]);
+        assert_eq!(
+            render(template, &vars(&[("input", "here")])).unwrap(),
+            "Use {{ }} or {{not a var}} and {single} braces here"
+        );
+        assert_eq!(render("open {{input", &vars(&[])).unwrap(), "open {{input");
+    }
+
+    #[test]
+    fn test_placeholders_are_deduplicated() {
+        assert_eq!(
+            placeholders("{{a}} {{b-c}} {{a}} {{d_e}}"),
+            vec!["a", "b-c", "d_e"]
+        );
+        assert!(placeholders("No placeholders here.").is_empty());
+    }
+}